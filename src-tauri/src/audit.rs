@@ -0,0 +1,171 @@
+use rusqlite::{Result as SqliteResult, ToSql};
+use serde::Deserialize;
+
+use crate::ob::AuditEntry;
+use crate::sqlite::SqliteManager;
+
+/// Narrows the audit log. `key` matches any key containing it, `operation`
+/// is compared regardless of case, and `since`/`until` are Unix milliseconds.
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    pub connection_id: Option<i64>,
+    pub key: Option<String>,
+    pub operation: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Entries matching `filter`, newest first.
+pub fn query(
+    manager: &SqliteManager,
+    filter: &AuditFilter,
+    limit: Option<u32>,
+) -> SqliteResult<Vec<AuditEntry>> {
+    let mut clauses = vec!["1"];
+    let mut params: Vec<&dyn ToSql> = Vec::new();
+
+    if let Some(connection_id) = &filter.connection_id {
+        clauses.push("json_extract(data, '$.connection_id') = ?");
+        params.push(connection_id);
+    }
+    if let Some(key) = &filter.key {
+        clauses.push("instr(json_extract(data, '$.key'), ?) > 0");
+        params.push(key);
+    }
+    if let Some(operation) = &filter.operation {
+        clauses.push("upper(json_extract(data, '$.operation')) = upper(?)");
+        params.push(operation);
+    }
+    if let Some(since) = &filter.since {
+        clauses.push("json_extract(data, '$.timestamp') >= ?");
+        params.push(since);
+    }
+    if let Some(until) = &filter.until {
+        clauses.push("json_extract(data, '$.timestamp') < ?");
+        params.push(until);
+    }
+
+    let mut sql = format!("{} ORDER BY id DESC", clauses.join(" AND "));
+    if let Some(limit) = &limit {
+        sql.push_str(" LIMIT ?");
+        params.push(limit);
+    }
+
+    manager.query(&sql, &params)
+}
+
+pub fn export(entries: &[AuditEntry], format: ExportFormat) -> Result<String, String> {
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(entries).map_err(|e| e.to_string()),
+        ExportFormat::Csv => Ok(to_csv(entries)),
+    }
+}
+
+fn to_csv(entries: &[AuditEntry]) -> String {
+    let mut csv = String::from(
+        "timestamp,connection_id,connection_name,user,operation,key,previous_digest\n",
+    );
+
+    for entry in entries {
+        let fields = [
+            entry.timestamp.to_string(),
+            entry.connection_id.to_string(),
+            csv_field(&entry.connection_name),
+            csv_field(&entry.user),
+            csv_field(&entry.operation),
+            csv_field(entry.key.as_deref().unwrap_or_default()),
+            entry.previous_digest.clone().unwrap_or_default(),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::fs;
+
+    const TEST_DIR: &str = "./test_audit_db";
+
+    fn entry(connection_id: i64, key: &str, operation: &str, timestamp: i64) -> AuditEntry {
+        AuditEntry {
+            id: 0,
+            connection_id,
+            connection_name: format!("conn {}", connection_id),
+            user: "tester".to_string(),
+            key: Some(key.to_string()),
+            operation: operation.to_string(),
+            previous_digest: None,
+            timestamp,
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn filter_entries() {
+        let _ = fs::remove_dir_all(TEST_DIR);
+        fs::create_dir_all(TEST_DIR).unwrap();
+        let manager = SqliteManager {
+            path: format!("{}/test.db", TEST_DIR),
+        };
+        manager.init_table::<AuditEntry>().unwrap();
+
+        manager.insert(&entry(1, "user:1", "SET", 100)).unwrap();
+        manager.insert(&entry(1, "user:2", "DEL", 200)).unwrap();
+        manager.insert(&entry(2, "user:1", "SET", 300)).unwrap();
+
+        let filter = AuditFilter {
+            connection_id: Some(1),
+            ..Default::default()
+        };
+        let keys: Vec<String> = query(&manager, &filter, None)
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| e.key)
+            .collect();
+        assert_eq!(keys, vec!["user:2", "user:1"]);
+
+        let filter = AuditFilter {
+            key: Some("user:1".to_string()),
+            operation: Some("set".to_string()),
+            since: Some(150),
+            ..Default::default()
+        };
+        let found = query(&manager, &filter, Some(10)).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].connection_id, 2);
+
+        let _ = fs::remove_dir_all(TEST_DIR);
+    }
+
+    #[test]
+    fn csv_export() {
+        let mut entry = entry(1, "a,\"b\"", "HSET", 5);
+        entry.previous_digest = Some("ab12".to_string());
+
+        let csv = export(&[entry], ExportFormat::Csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "5,1,conn 1,tester,HSET,\"a,\"\"b\"\"\",ab12"
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::RedisValue;
+
+/// Commands that would take over the shared connection or change its state
+/// for every other caller. Transactions are included because commands sent by
+/// the rest of the app would be queued into them, and blocking commands
+/// because everything else sent on the connection would wait behind them.
+const UNSUPPORTED_COMMANDS: [&str; 26] = [
+    "SUBSCRIBE",
+    "PSUBSCRIBE",
+    "SSUBSCRIBE",
+    "MONITOR",
+    "SYNC",
+    "PSYNC",
+    "SELECT",
+    "RESET",
+    "QUIT",
+    "HELLO",
+    "AUTH",
+    "MULTI",
+    "EXEC",
+    "DISCARD",
+    "WATCH",
+    "UNWATCH",
+    "BLPOP",
+    "BRPOP",
+    "BRPOPLPUSH",
+    "BLMOVE",
+    "BLMPOP",
+    "BZPOPMIN",
+    "BZPOPMAX",
+    "BZMPOP",
+    "WAIT",
+    "WAITAOF",
+];
+
+/// Subcommands that change the state of the shared connection.
+const UNSUPPORTED_SUBCOMMANDS: [(&str, &str); 3] = [
+    ("CLIENT", "REPLY"),
+    ("CLIENT", "SETNAME"),
+    ("CLIENT", "TRACKING"),
+];
+
+/// Commands that block when given the BLOCK option, which comes before
+/// their STREAMS.
+const BLOCKING_OPTION_COMMANDS: [&str; 2] = ["XREAD", "XREADGROUP"];
+
+/// A command reply, keeping the shape and types of what the server sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum RedisReply {
+    Nil,
+    Integer(i64),
+    BulkString(RedisValue),
+    SimpleString(String),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    Array(Vec<RedisReply>),
+    Set(Vec<RedisReply>),
+    Map(Vec<(RedisReply, RedisReply)>),
+    Error(String),
+}
+
+impl From<redis::Value> for RedisReply {
+    fn from(value: redis::Value) -> Self {
+        match value {
+            redis::Value::Nil => RedisReply::Nil,
+            redis::Value::Int(n) => RedisReply::Integer(n),
+            redis::Value::BulkString(bytes) => {
+                RedisReply::BulkString(RedisValue::from_bytes(bytes))
+            }
+            redis::Value::SimpleString(text) => RedisReply::SimpleString(text),
+            redis::Value::Okay => RedisReply::SimpleString(String::from("OK")),
+            redis::Value::Double(f) => RedisReply::Double(f),
+            redis::Value::Boolean(b) => RedisReply::Boolean(b),
+            redis::Value::BigNumber(n) => RedisReply::BigNumber(n.to_string()),
+            redis::Value::VerbatimString { text, .. } => {
+                RedisReply::BulkString(text.as_str().into())
+            }
+            redis::Value::Array(items) | redis::Value::Push { data: items, .. } => {
+                RedisReply::Array(items.into_iter().map(RedisReply::from).collect())
+            }
+            redis::Value::Set(items) => {
+                RedisReply::Set(items.into_iter().map(RedisReply::from).collect())
+            }
+            redis::Value::Map(entries) => RedisReply::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.into(), value.into()))
+                    .collect(),
+            ),
+            redis::Value::Attribute { data, .. } => RedisReply::from(*data),
+            redis::Value::ServerError(error) => RedisReply::Error(
+                format!("{} {}", error.code(), error.details().unwrap_or_default())
+                    .trim_end()
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+/// Turns an error returned by the server into a reply, passing client and
+/// connection errors through.
+pub fn error_reply(error: redis::RedisError) -> redis::RedisResult<RedisReply> {
+    match error.code() {
+        Some(code) => Ok(RedisReply::Error(
+            format!("{} {}", code, error.detail().unwrap_or_default())
+                .trim_end()
+                .to_string(),
+        )),
+        None => Err(error),
+    }
+}
+
+pub fn check_supported(args: &[Vec<u8>]) -> Result<(), String> {
+    let name = args
+        .first()
+        .map(|name| String::from_utf8_lossy(name).to_uppercase())
+        .ok_or_else(|| String::from("Empty command"))?;
+
+    if UNSUPPORTED_COMMANDS.contains(&name.as_str()) {
+        return Err(format!("{} is not supported in the console", name));
+    }
+
+    if let Some(subcommand) = args.get(1) {
+        let subcommand = String::from_utf8_lossy(subcommand).to_uppercase();
+        if UNSUPPORTED_SUBCOMMANDS.contains(&(name.as_str(), subcommand.as_str())) {
+            return Err(format!(
+                "{} {} is not supported in the console",
+                name, subcommand
+            ));
+        }
+    }
+
+    let blocks = BLOCKING_OPTION_COMMANDS.contains(&name.as_str())
+        && args[1..]
+            .iter()
+            .take_while(|arg| !arg.eq_ignore_ascii_case(b"STREAMS"))
+            .any(|arg| arg.eq_ignore_ascii_case(b"BLOCK"));
+    if blocks {
+        return Err(format!(
+            "{} with BLOCK is not supported in the console",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Splits a command line the way redis-cli does: arguments are separated by
+/// whitespace, double quotes support `\n`, `\t`, `\xHH` and friends, single
+/// quotes only `\'`.
+pub fn tokenize(line: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.bytes().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+
+        if chars.peek().is_none() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+
+        loop {
+            match chars.next() {
+                None => break,
+                Some(c) if c.is_ascii_whitespace() => break,
+                Some(b'"') => {
+                    loop {
+                        match chars.next() {
+                            None => return Err(String::from("Unbalanced quotes")),
+                            Some(b'"') => break,
+                            Some(b'\\') => match chars.next() {
+                                None => return Err(String::from("Unbalanced quotes")),
+                                Some(b'x') => {
+                                    let hex = [chars.next(), chars.next()];
+                                    match hex {
+                                        [Some(hi), Some(lo)]
+                                            if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() =>
+                                        {
+                                            let digits = [hi, lo];
+                                            let hex = std::str::from_utf8(&digits).unwrap();
+                                            arg.push(u8::from_str_radix(hex, 16).unwrap());
+                                        }
+                                        _ => return Err(String::from("Invalid \\x escape")),
+                                    }
+                                }
+                                Some(b'n') => arg.push(b'\n'),
+                                Some(b'r') => arg.push(b'\r'),
+                                Some(b't') => arg.push(b'\t'),
+                                Some(b'b') => arg.push(0x08),
+                                Some(b'a') => arg.push(0x07),
+                                Some(c) => arg.push(c),
+                            },
+                            Some(c) => arg.push(c),
+                        }
+                    }
+                    closing_quote(chars.peek())?;
+                }
+                Some(b'\'') => {
+                    loop {
+                        match chars.next() {
+                            None => return Err(String::from("Unbalanced quotes")),
+                            Some(b'\'') => break,
+                            Some(b'\\') if chars.peek() == Some(&b'\'') => {
+                                chars.next();
+                                arg.push(b'\'');
+                            }
+                            Some(c) => arg.push(c),
+                        }
+                    }
+                    closing_quote(chars.peek())?;
+                }
+                Some(c) => arg.push(c),
+            }
+        }
+
+        args.push(arg);
+    }
+}
+
+/// Joins arguments into a line that `tokenize` splits back into the same
+/// bytes, quoting only the arguments that need it.
+pub fn format_command(args: &[&[u8]]) -> String {
+    args.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn quote(arg: &[u8]) -> String {
+    if !arg.is_empty()
+        && arg
+            .iter()
+            .all(|c| c.is_ascii_graphic() && !matches!(c, b'"' | b'\''))
+    {
+        return String::from_utf8_lossy(arg).into_owned();
+    }
+
+    let mut quoted = String::from("\"");
+    match std::str::from_utf8(arg) {
+        Ok(text) => text.chars().for_each(|c| escape(c, &mut quoted)),
+        Err(_) => arg.iter().for_each(|&c| match c {
+            c if c.is_ascii() => escape(c as char, &mut quoted),
+            c => quoted.push_str(&format!("\\x{:02x}", c)),
+        }),
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn escape(c: char, out: &mut String) {
+    match c {
+        '"' => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u8)),
+        c => out.push(c),
+    }
+}
+
+fn closing_quote(next: Option<&u8>) -> Result<(), String> {
+    match next {
+        Some(c) if !c.is_ascii_whitespace() => {
+            Err(String::from("Closing quote must be followed by a space"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_arguments() {
+        assert_eq!(
+            tokenize("  SET  key   value ").unwrap(),
+            vec![b"SET".to_vec(), b"key".to_vec(), b"value".to_vec()]
+        );
+        assert!(tokenize("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quoted_arguments() {
+        assert_eq!(
+            tokenize(r#"SET "my key" "line\nnext\x00\xff""#).unwrap(),
+            vec![
+                b"SET".to_vec(),
+                b"my key".to_vec(),
+                b"line\nnext\x00\xff".to_vec()
+            ]
+        );
+        assert_eq!(
+            tokenize(r#"SET k 'it\'s "raw" \n'"#).unwrap()[2],
+            br#"it's "raw" \n"#.to_vec()
+        );
+        assert_eq!(tokenize(r#"SET k """#).unwrap()[2], b"".to_vec());
+    }
+
+    #[test]
+    fn invalid_quotes() {
+        assert!(tokenize(r#"SET "key"#).is_err());
+        assert!(tokenize(r#"SET "key"value"#).is_err());
+        assert!(tokenize(r#"SET 'key"#).is_err());
+        assert!(tokenize(r#"SET "\xzz""#).is_err());
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let args: Vec<&[u8]> = vec![
+            b"SET",
+            b"my key",
+            b"",
+            b"it's \"quoted\" \\ h\xc3\xa9",
+            b"\x00\xff\r\n",
+            b"a\\b",
+        ];
+
+        let line = format_command(&args);
+        assert_eq!(tokenize(&line).unwrap(), args);
+        assert!(line.starts_with("SET \"my key\" \"\" "));
+    }
+
+    #[test]
+    fn unsupported_commands() {
+        assert!(check_supported(&tokenize("subscribe channel").unwrap()).is_err());
+        assert!(check_supported(&tokenize("GET key").unwrap()).is_ok());
+        assert!(check_supported(&[]).is_err());
+
+        for line in [
+            "MULTI",
+            "watch key",
+            "BLPOP list 0",
+            "BZPOPMIN zset 1",
+            "WAIT 1 0",
+            "HELLO 3",
+            "AUTH user password",
+            "client reply off",
+            "CLIENT SETNAME console",
+            "XREAD COUNT 1 BLOCK 0 STREAMS stream $",
+            "xreadgroup GROUP g c block 100 STREAMS stream >",
+        ] {
+            assert!(
+                check_supported(&tokenize(line).unwrap()).is_err(),
+                "{}",
+                line
+            );
+        }
+
+        for line in [
+            "CLIENT LIST",
+            "XREAD COUNT 1 STREAMS stream 0",
+            "XREAD STREAMS block 0",
+        ] {
+            assert!(
+                check_supported(&tokenize(line).unwrap()).is_ok(),
+                "{}",
+                line
+            );
+        }
+    }
+}
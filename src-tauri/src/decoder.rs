@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+mod gzip;
+mod java;
+mod json;
+mod msgpack;
+mod php;
+mod protobuf;
+mod schema;
+
+pub use schema::{load_descriptor_set, validate_schema, SchemaDecoder};
+
+/// How deeply a decoded value may nest, the same limit serde_json puts on
+/// the edited views sent back for encoding.
+const MAX_DEPTH: usize = 128;
+
+/// How many layers are detected before giving up, so a value that decodes to
+/// itself cannot be unwrapped forever.
+const MAX_LAYERS: usize = 8;
+
+fn too_deep_error() -> String {
+    format!("Value is nested more than {} levels deep", MAX_DEPTH)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueFormat {
+    Gzip,
+    Json,
+    MessagePack,
+    Protobuf,
+    Php,
+    Java,
+}
+
+/// What a decoder produced: more bytes for the next decoder (gzip), or the
+/// structured view that ends the chain. `ReadOnly` is a view that would not
+/// encode back to the bytes it was decoded from.
+#[derive(Debug)]
+pub enum Decoded {
+    Bytes(Vec<u8>),
+    Value(Value),
+    ReadOnly(Value),
+}
+
+impl Decoded {
+    fn into_bytes(self) -> Result<Vec<u8>, String> {
+        match self {
+            Decoded::Bytes(bytes) => Ok(bytes),
+            Decoded::Value(Value::String(text)) => Ok(text.into_bytes()),
+            Decoded::Value(_) | Decoded::ReadOnly(_) => Err(String::from("Expected raw bytes")),
+        }
+    }
+
+    fn into_value(self) -> Result<Value, String> {
+        match self {
+            Decoded::Value(value) | Decoded::ReadOnly(value) => Ok(value),
+            Decoded::Bytes(_) => Err(String::from("Expected a structured value")),
+        }
+    }
+}
+
+pub trait Decoder: Send + Sync {
+    fn format(&self) -> ValueFormat;
+
+    /// Whether `bytes` look like this format, used when no format is given.
+    fn detect(&self, bytes: &[u8]) -> bool;
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String>;
+
+    fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String>;
+
+    /// Whether decoded values cannot be encoded back, so they can be viewed
+    /// but not edited.
+    fn read_only(&self) -> bool {
+        false
+    }
+}
+
+/// A decoded value. `formats` lists the decoders that were applied, outermost
+/// first, and is what has to be passed back to re-encode an edited `view`.
+/// `read_only` is set when one of them cannot encode, or the value would not
+/// encode back to the same bytes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecodedValue {
+    pub formats: Vec<ValueFormat>,
+    pub view: Value,
+    pub pretty: String,
+    pub read_only: bool,
+}
+
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn Decoder>>,
+}
+
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        let mut registry = DecoderRegistry::new();
+        registry.register(Box::new(gzip::GzipDecoder));
+        registry.register(Box::new(java::JavaDecoder));
+        registry.register(Box::new(php::PhpDecoder));
+        registry.register(Box::new(json::JsonDecoder));
+        registry.register(Box::new(msgpack::MessagePackDecoder));
+        registry.register(Box::new(protobuf::ProtobufDecoder));
+        registry
+    }
+}
+
+impl DecoderRegistry {
+    pub fn new() -> Self {
+        DecoderRegistry {
+            decoders: Vec::new(),
+        }
+    }
+
+    /// Adds a decoder. Detection tries decoders in registration order, and a
+    /// later decoder replaces an earlier one for the same format.
+    pub fn register(&mut self, decoder: Box<dyn Decoder>) {
+        match self
+            .decoders
+            .iter()
+            .position(|d| d.format() == decoder.format())
+        {
+            Some(index) => self.decoders[index] = decoder,
+            None => self.decoders.push(decoder),
+        }
+    }
+
+    /// The registered decoders, with `overrides` taking the place of the
+    /// decoder registered for the same format.
+    fn decoders<'a>(
+        &'a self,
+        overrides: &'a [&'a dyn Decoder],
+    ) -> impl Iterator<Item = &'a dyn Decoder> {
+        self.decoders.iter().map(move |d| {
+            overrides
+                .iter()
+                .find(|o| o.format() == d.format())
+                .copied()
+                .unwrap_or(d.as_ref())
+        })
+    }
+
+    fn get<'a>(
+        &'a self,
+        format: ValueFormat,
+        overrides: &'a [&'a dyn Decoder],
+    ) -> Result<&'a dyn Decoder, String> {
+        self.decoders(overrides)
+            .find(|d| d.format() == format)
+            .ok_or_else(|| format!("No decoder registered for {:?}", format))
+    }
+
+    /// Decodes `bytes` with `formats` applied in order, or by detecting up to
+    /// `MAX_LAYERS` layers when no formats are given. Bytes left over after
+    /// the last layer are shown as text.
+    pub fn decode(
+        &self,
+        bytes: &[u8],
+        formats: Option<&[ValueFormat]>,
+        overrides: &[&dyn Decoder],
+    ) -> Result<DecodedValue, String> {
+        let mut applied = Vec::new();
+        let mut read_only = false;
+        let mut bytes = bytes.to_vec();
+
+        let view = loop {
+            let decoder = match formats {
+                Some(formats) => match formats.get(applied.len()) {
+                    Some(format) => Some(self.get(*format, overrides)?),
+                    None => None,
+                },
+                None => match self.decoders(overrides).find(|d| d.detect(&bytes)) {
+                    Some(_) if applied.len() == MAX_LAYERS => {
+                        return Err(format!(
+                            "Value is wrapped in more than {} layers",
+                            MAX_LAYERS
+                        ));
+                    }
+                    decoder => decoder,
+                },
+            };
+
+            let Some(decoder) = decoder else {
+                if applied.is_empty() {
+                    return Err(String::from("Unrecognized value format"));
+                }
+
+                break Value::String(
+                    String::from_utf8(bytes)
+                        .map_err(|_| String::from("Decoded value is not valid UTF-8"))?,
+                );
+            };
+
+            applied.push(decoder.format());
+            read_only |= decoder.read_only();
+
+            match decoder.decode(&bytes)? {
+                Decoded::Bytes(inner) => bytes = inner,
+                Decoded::Value(value) => break value,
+                Decoded::ReadOnly(value) => {
+                    read_only = true;
+                    break value;
+                }
+            }
+        };
+
+        if let Some(formats) = formats {
+            if formats.len() > applied.len() {
+                return Err(format!(
+                    "{:?} cannot be applied to a decoded value",
+                    formats[applied.len()]
+                ));
+            }
+        }
+
+        let pretty = serde_json::to_string_pretty(&view).map_err(|e| e.to_string())?;
+
+        Ok(DecodedValue {
+            formats: applied,
+            view,
+            pretty,
+            read_only,
+        })
+    }
+
+    /// Encodes an edited `view` back through `formats`, innermost first.
+    /// Fails up front when one of them is read-only.
+    pub fn encode(
+        &self,
+        formats: &[ValueFormat],
+        view: Value,
+        overrides: &[&dyn Decoder],
+    ) -> Result<Vec<u8>, String> {
+        let decoders = formats
+            .iter()
+            .map(|format| self.get(*format, overrides))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(decoder) = decoders.iter().find(|decoder| decoder.read_only()) {
+            return Err(format!("{:?} values are read-only", decoder.format()));
+        }
+
+        let mut decoded = Decoded::Value(view);
+        for decoder in decoders.iter().rev() {
+            decoded = Decoded::Bytes(decoder.encode(decoded)?);
+        }
+
+        decoded.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn detect_nested_formats() {
+        let registry = DecoderRegistry::default();
+        let view = json!({ "name": "setui", "tags": ["redis", "gui"] });
+
+        let bytes = registry
+            .encode(&[ValueFormat::Gzip, ValueFormat::Json], view.clone(), &[])
+            .unwrap();
+        let decoded = registry.decode(&bytes, None, &[]).unwrap();
+        assert_eq!(decoded.formats, vec![ValueFormat::Gzip, ValueFormat::Json]);
+        assert_eq!(decoded.view, view);
+
+        let bytes = registry
+            .encode(&[ValueFormat::MessagePack], view.clone(), &[])
+            .unwrap();
+        let decoded = registry.decode(&bytes, None, &[]).unwrap();
+        assert_eq!(decoded.formats, vec![ValueFormat::MessagePack]);
+        assert_eq!(decoded.view, view);
+        assert!(!decoded.read_only);
+    }
+
+    #[test]
+    fn read_only_formats() {
+        let registry = DecoderRegistry::default();
+        let err = registry
+            .encode(&[ValueFormat::Gzip, ValueFormat::Java], json!(null), &[])
+            .unwrap_err();
+        assert_eq!(err, "Java values are read-only");
+    }
+
+    #[test]
+    fn explicit_formats() {
+        let registry = DecoderRegistry::default();
+
+        let decoded = registry
+            .decode(br#"{"a":1}"#, Some(&[ValueFormat::Json]), &[])
+            .unwrap();
+        assert_eq!(decoded.view, json!({ "a": 1 }));
+        assert_eq!(decoded.pretty, "{\n  \"a\": 1\n}");
+
+        assert!(registry
+            .decode(br#"{"a":1}"#, Some(&[ValueFormat::Gzip]), &[])
+            .is_err());
+        assert!(registry
+            .decode(
+                br#"{"a":1}"#,
+                Some(&[ValueFormat::Json, ValueFormat::Gzip]),
+                &[]
+            )
+            .is_err());
+    }
+
+    /// Unwraps to the bytes it was given, like a gzip quine.
+    struct SelfNesting;
+
+    impl Decoder for SelfNesting {
+        fn format(&self) -> ValueFormat {
+            ValueFormat::Gzip
+        }
+
+        fn detect(&self, _bytes: &[u8]) -> bool {
+            true
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+            Ok(Decoded::Bytes(bytes.to_vec()))
+        }
+
+        fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String> {
+            decoded.into_bytes()
+        }
+    }
+
+    #[test]
+    fn layer_limit() {
+        let registry = DecoderRegistry::default();
+        let err = registry
+            .decode(b"quine", None, &[&SelfNesting])
+            .unwrap_err();
+        assert_eq!(err, "Value is wrapped in more than 8 layers");
+
+        let formats = vec![ValueFormat::Gzip; MAX_LAYERS + 1];
+        let decoded = registry
+            .decode(b"quine", Some(&formats), &[&SelfNesting])
+            .unwrap();
+        assert_eq!(decoded.view, json!("quine"));
+    }
+
+    #[test]
+    fn unrecognized_value() {
+        let registry = DecoderRegistry::default();
+        assert!(registry.decode(b"plain text", None, &[]).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+
+use super::{Decoded, Decoder, ValueFormat};
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// How large a value may inflate to, so a small compressed value cannot take
+/// all memory when opened.
+const MAX_INFLATED_BYTES: u64 = 64 * 1024 * 1024;
+
+pub struct GzipDecoder;
+
+fn inflate(bytes: &[u8], limit: u64) -> Result<Vec<u8>, String> {
+    let mut inflated = Vec::new();
+    GzDecoder::new(bytes)
+        .take(limit + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| e.to_string())?;
+
+    if inflated.len() as u64 > limit {
+        return Err(format!("Value inflates to more than {} bytes", limit));
+    }
+
+    Ok(inflated)
+}
+
+impl Decoder for GzipDecoder {
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Gzip
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(&MAGIC)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+        inflate(bytes, MAX_INFLATED_BYTES).map(Decoded::Bytes)
+    }
+
+    fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&decoded.into_bytes()?)
+            .map_err(|e| e.to_string())?;
+
+        encoder.finish().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let bytes = GzipDecoder
+            .encode(Decoded::Bytes(b"hello".to_vec()))
+            .unwrap();
+        assert!(GzipDecoder.detect(&bytes));
+
+        match GzipDecoder.decode(&bytes).unwrap() {
+            Decoded::Bytes(inflated) => assert_eq!(inflated, b"hello"),
+            _ => panic!("expected bytes"),
+        }
+    }
+
+    #[test]
+    fn inflated_size_limit() {
+        let bytes = GzipDecoder.encode(Decoded::Bytes(vec![0; 1024])).unwrap();
+
+        assert_eq!(inflate(&bytes, 1024).unwrap().len(), 1024);
+        assert!(inflate(&bytes, 1023).is_err());
+    }
+}
@@ -0,0 +1,632 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{Map, Number, Value};
+use std::rc::Rc;
+
+use super::{too_deep_error, Decoded, Decoder, ValueFormat, MAX_DEPTH};
+
+const MAGIC: [u8; 4] = [0xac, 0xed, 0x00, 0x05];
+const BASE_HANDLE: i32 = 0x7e0000;
+/// How many JSON nodes a stream may decode to, with strings counting one more
+/// node for every `STRING_NODE_BYTES`. References repeat what they point to,
+/// so a small stream could otherwise expand exponentially.
+const MAX_NODES: usize = 1_000_000;
+const STRING_NODE_BYTES: usize = 64;
+
+const TC_NULL: u8 = 0x70;
+const TC_REFERENCE: u8 = 0x71;
+const TC_CLASSDESC: u8 = 0x72;
+const TC_OBJECT: u8 = 0x73;
+const TC_STRING: u8 = 0x74;
+const TC_ARRAY: u8 = 0x75;
+const TC_CLASS: u8 = 0x76;
+const TC_BLOCKDATA: u8 = 0x77;
+const TC_ENDBLOCKDATA: u8 = 0x78;
+const TC_RESET: u8 = 0x79;
+const TC_BLOCKDATALONG: u8 = 0x7a;
+const TC_LONGSTRING: u8 = 0x7c;
+const TC_PROXYCLASSDESC: u8 = 0x7d;
+const TC_ENUM: u8 = 0x7e;
+
+const SC_WRITE_METHOD: u8 = 0x01;
+const SC_SERIALIZABLE: u8 = 0x02;
+const SC_EXTERNALIZABLE: u8 = 0x04;
+const SC_BLOCK_DATA: u8 = 0x08;
+
+/// Java object serialization streams. Objects are shown with their class name
+/// under `@class` and anything written by a custom `writeObject` under
+/// `@annotations`. A reference shows the value it points to, or null when
+/// that value contains the reference. The format carries too much type
+/// information to rebuild from JSON, so these values are read-only.
+pub struct JavaDecoder;
+
+struct ClassDesc {
+    name: String,
+    flags: u8,
+    fields: Vec<(u8, String)>,
+    super_class: Option<Rc<ClassDesc>>,
+}
+
+/// A value read from the stream. Objects, arrays and enum constants are kept
+/// once in the handle table and pointed to, so references to them are only
+/// expanded when the JSON is built.
+enum Node {
+    Value(Value),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+    Handle(usize),
+}
+
+enum Handle {
+    Class(Rc<ClassDesc>),
+    Value(Node),
+}
+
+enum Content {
+    Value(Node),
+    BlockData(Vec<u8>),
+    End,
+}
+
+/// Reads a stream into nodes. `base` is where the handles assigned since the
+/// last reset start in `handles`, which keeps earlier ones for the nodes that
+/// point to them.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    handles: Vec<Handle>,
+    base: usize,
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self.pos.saturating_add(len);
+        let data = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| String::from("Unexpected end of stream"))?;
+        self.pos = end;
+        Ok(data)
+    }
+
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.read::<1>()?[0])
+    }
+
+    fn utf(&mut self, len: usize) -> Result<String, String> {
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn short_utf(&mut self) -> Result<String, String> {
+        let len = u16::from_be_bytes(self.read()?) as usize;
+        self.utf(len)
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        if self.depth == MAX_DEPTH {
+            return Err(too_deep_error());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn new_handle(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len() - 1
+    }
+
+    /// Reads a handle number, returning its index in `handles`.
+    fn reference(&mut self) -> Result<usize, String> {
+        let handle = i32::from_be_bytes(self.read()?) - BASE_HANDLE;
+        usize::try_from(handle)
+            .ok()
+            .map(|handle| self.base + handle)
+            .filter(|handle| *handle < self.handles.len())
+            .ok_or_else(|| String::from("Invalid handle"))
+    }
+
+    fn class_desc(&mut self) -> Result<Option<Rc<ClassDesc>>, String> {
+        let tc = self.u8()?;
+        self.class_desc_from(tc)
+    }
+
+    fn class_desc_from(&mut self, tc: u8) -> Result<Option<Rc<ClassDesc>>, String> {
+        match tc {
+            TC_NULL => Ok(None),
+            TC_REFERENCE => {
+                let handle = self.reference()?;
+                match &self.handles[handle] {
+                    Handle::Class(desc) => Ok(Some(desc.clone())),
+                    Handle::Value(_) => Err(String::from("Handle is not a class")),
+                }
+            }
+            tc => {
+                self.enter()?;
+                let desc = self.new_class_desc(tc)?;
+                self.depth -= 1;
+                Ok(Some(desc))
+            }
+        }
+    }
+
+    fn new_class_desc(&mut self, tc: u8) -> Result<Rc<ClassDesc>, String> {
+        match tc {
+            TC_CLASSDESC => {
+                let name = self.short_utf()?;
+                self.take(8)?;
+                let handle = self.new_handle(Handle::Value(Node::Value(Value::Null)));
+
+                let flags = self.u8()?;
+                let count = u16::from_be_bytes(self.read()?);
+                let mut fields = Vec::new();
+                for _ in 0..count {
+                    let type_code = self.u8()?;
+                    let field = self.short_utf()?;
+                    if matches!(type_code, b'L' | b'[') {
+                        self.object()?;
+                    }
+                    fields.push((type_code, field));
+                }
+
+                self.annotations()?;
+                let super_class = self.class_desc()?;
+
+                let desc = Rc::new(ClassDesc {
+                    name,
+                    flags,
+                    fields,
+                    super_class,
+                });
+                self.handles[handle] = Handle::Class(desc.clone());
+                Ok(desc)
+            }
+            TC_PROXYCLASSDESC => {
+                let handle = self.new_handle(Handle::Value(Node::Value(Value::Null)));
+                let count = i32::from_be_bytes(self.read()?);
+                for _ in 0..count {
+                    self.short_utf()?;
+                }
+
+                self.annotations()?;
+                let super_class = self.class_desc()?;
+
+                let desc = Rc::new(ClassDesc {
+                    name: String::from("$Proxy"),
+                    flags: SC_SERIALIZABLE,
+                    fields: Vec::new(),
+                    super_class,
+                });
+                self.handles[handle] = Handle::Class(desc.clone());
+                Ok(desc)
+            }
+            tc => Err(format!("Expected a class descriptor, found {:#04x}", tc)),
+        }
+    }
+
+    fn content(&mut self) -> Result<Content, String> {
+        let tc = self.u8()?;
+
+        match tc {
+            TC_ENDBLOCKDATA => Ok(Content::End),
+            TC_BLOCKDATA => {
+                let len = self.u8()? as usize;
+                Ok(Content::BlockData(self.take(len)?.to_vec()))
+            }
+            TC_BLOCKDATALONG => {
+                let len = u32::from_be_bytes(self.read()?) as usize;
+                Ok(Content::BlockData(self.take(len)?.to_vec()))
+            }
+            tc => self.object_from(tc).map(Content::Value),
+        }
+    }
+
+    /// Reads the optional data after a class or object up to the end marker.
+    fn annotations(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+
+        loop {
+            match self.content()? {
+                Content::End => return Ok(nodes),
+                Content::BlockData(data) => nodes.push(Node::Value(block_data(data))),
+                Content::Value(node) => nodes.push(node),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Node, String> {
+        let tc = self.u8()?;
+        self.object_from(tc)
+    }
+
+    fn object_from(&mut self, tc: u8) -> Result<Node, String> {
+        self.enter()?;
+        let node = self.read_object(tc)?;
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    fn read_object(&mut self, tc: u8) -> Result<Node, String> {
+        match tc {
+            TC_NULL => Ok(Node::Value(Value::Null)),
+            TC_REFERENCE => Ok(Node::Handle(self.reference()?)),
+            TC_STRING => {
+                let text = self.short_utf()?;
+                Ok(self.string_handle(text))
+            }
+            TC_LONGSTRING => {
+                let len = u64::from_be_bytes(self.read()?) as usize;
+                let text = self.utf(len)?;
+                Ok(self.string_handle(text))
+            }
+            TC_CLASS => {
+                let desc = self.class_desc()?;
+                Ok(self.string_handle(desc.map(|d| d.name.clone()).unwrap_or_default()))
+            }
+            TC_CLASSDESC | TC_PROXYCLASSDESC => {
+                let desc = self.class_desc_from(tc)?;
+                Ok(Node::Value(Value::String(
+                    desc.map(|d| d.name.clone()).unwrap_or_default(),
+                )))
+            }
+            TC_ENUM => {
+                self.class_desc()?;
+                let handle = self.new_handle(Handle::Value(Node::Value(Value::Null)));
+                let constant = self.object()?;
+                self.handles[handle] = Handle::Value(constant);
+                Ok(Node::Handle(handle))
+            }
+            TC_ARRAY => {
+                let desc = self
+                    .class_desc()?
+                    .ok_or_else(|| String::from("Array without a class"))?;
+                let handle = self.new_handle(Handle::Value(Node::Value(Value::Null)));
+
+                let size = i32::from_be_bytes(self.read()?).max(0);
+                let type_code = desc.name.as_bytes().get(1).copied().unwrap_or(b'L');
+                let items = (0..size)
+                    .map(|_| self.field(type_code))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.handles[handle] = Handle::Value(Node::Array(items));
+                Ok(Node::Handle(handle))
+            }
+            TC_OBJECT => {
+                let desc = self
+                    .class_desc()?
+                    .ok_or_else(|| String::from("Object without a class"))?;
+                let handle = self.new_handle(Handle::Value(Node::Value(Value::Null)));
+
+                let mut fields = vec![(
+                    String::from("@class"),
+                    Node::Value(Value::String(desc.name.clone())),
+                )];
+
+                let mut hierarchy = Vec::new();
+                let mut class = Some(desc);
+                while let Some(desc) = class {
+                    class = desc.super_class.clone();
+                    hierarchy.push(desc);
+                }
+
+                let mut annotations = Vec::new();
+                for desc in hierarchy.iter().rev() {
+                    if desc.flags & SC_EXTERNALIZABLE != 0 {
+                        if desc.flags & SC_BLOCK_DATA == 0 {
+                            return Err(format!("{} uses an old externalizable format", desc.name));
+                        }
+                        annotations.extend(self.annotations()?);
+                        continue;
+                    }
+
+                    if desc.flags & SC_SERIALIZABLE != 0 {
+                        for (type_code, name) in &desc.fields {
+                            let value = self.field(*type_code)?;
+                            fields.push((name.clone(), value));
+                        }
+                    }
+
+                    if desc.flags & SC_WRITE_METHOD != 0 {
+                        annotations.extend(self.annotations()?);
+                    }
+                }
+
+                if !annotations.is_empty() {
+                    fields.push((String::from("@annotations"), Node::Array(annotations)));
+                }
+
+                self.handles[handle] = Handle::Value(Node::Object(fields));
+                Ok(Node::Handle(handle))
+            }
+            TC_RESET => {
+                self.base = self.handles.len();
+                self.object()
+            }
+            tc => Err(format!("Unsupported type code {:#04x}", tc)),
+        }
+    }
+
+    fn string_handle(&mut self, text: String) -> Node {
+        Node::Handle(self.new_handle(Handle::Value(Node::Value(Value::String(text)))))
+    }
+
+    fn field(&mut self, type_code: u8) -> Result<Node, String> {
+        if matches!(type_code, b'L' | b'[') {
+            return self.object();
+        }
+
+        Ok(Node::Value(match type_code {
+            b'B' => Value::from(i8::from_be_bytes(self.read()?)),
+            b'C' => Value::String(
+                char::from_u32(u16::from_be_bytes(self.read()?) as u32)
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            b'D' => float(f64::from_be_bytes(self.read()?)),
+            b'F' => float(f32::from_be_bytes(self.read()?) as f64),
+            b'I' => Value::from(i32::from_be_bytes(self.read()?)),
+            b'J' => Value::from(i64::from_be_bytes(self.read()?)),
+            b'S' => Value::from(i16::from_be_bytes(self.read()?)),
+            b'Z' => Value::Bool(self.u8()? != 0),
+            type_code => return Err(format!("Unknown field type '{}'", type_code as char)),
+        }))
+    }
+}
+
+/// Turns nodes into JSON, expanding references. `expanding` marks the
+/// handles being built, so a reference back to one of them becomes null.
+struct Builder<'a> {
+    handles: &'a [Handle],
+    expanding: Vec<bool>,
+    depth: usize,
+    nodes: usize,
+}
+
+impl Builder<'_> {
+    fn count(&mut self, nodes: usize) -> Result<(), String> {
+        self.nodes += nodes;
+        if self.nodes > MAX_NODES {
+            return Err(format!("Value expands to more than {} nodes", MAX_NODES));
+        }
+        Ok(())
+    }
+
+    fn build(&mut self, node: &Node) -> Result<Value, String> {
+        self.count(1)?;
+
+        match node {
+            Node::Value(Value::String(text)) => {
+                self.count(text.len() / STRING_NODE_BYTES)?;
+                Ok(Value::String(text.clone()))
+            }
+            Node::Value(value) => Ok(value.clone()),
+            Node::Array(items) => self.nested(|builder| {
+                items
+                    .iter()
+                    .map(|item| builder.build(item))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Value::Array)
+            }),
+            Node::Object(fields) => self.nested(|builder| {
+                let mut map = Map::new();
+                for (name, value) in fields {
+                    map.insert(name.clone(), builder.build(value)?);
+                }
+                Ok(Value::Object(map))
+            }),
+            Node::Handle(handle) => match &self.handles[*handle] {
+                Handle::Class(desc) => Ok(Value::String(desc.name.clone())),
+                Handle::Value(_) if self.expanding[*handle] => Ok(Value::Null),
+                Handle::Value(node) => {
+                    self.expanding[*handle] = true;
+                    let value = self.build(node);
+                    self.expanding[*handle] = false;
+                    value
+                }
+            },
+        }
+    }
+
+    fn nested(
+        &mut self,
+        build: impl FnOnce(&mut Self) -> Result<Value, String>,
+    ) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(too_deep_error());
+        }
+
+        self.depth += 1;
+        let value = build(self)?;
+        self.depth -= 1;
+        Ok(value)
+    }
+}
+
+fn float(f: f64) -> Value {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+fn block_data(data: Vec<u8>) -> Value {
+    let mut map = Map::new();
+    map.insert(
+        String::from("@blockdata"),
+        Value::String(BASE64.encode(data)),
+    );
+    Value::Object(map)
+}
+
+impl Decoder for JavaDecoder {
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Java
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(&MAGIC)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(String::from("Not a Java serialization stream"));
+        }
+
+        let mut reader = Reader {
+            bytes,
+            pos: MAGIC.len(),
+            handles: Vec::new(),
+            base: 0,
+            depth: 0,
+        };
+
+        let mut nodes = Vec::new();
+        while reader.pos < bytes.len() {
+            match reader.content()? {
+                Content::Value(node) => nodes.push(node),
+                Content::BlockData(data) => nodes.push(Node::Value(block_data(data))),
+                Content::End => return Err(String::from("Unexpected end of block data")),
+            }
+        }
+
+        let mut builder = Builder {
+            handles: &reader.handles,
+            expanding: vec![false; reader.handles.len()],
+            depth: 0,
+            nodes: 0,
+        };
+        let mut values = nodes
+            .iter()
+            .map(|node| builder.build(node))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Decoded::Value(match values.len() {
+            1 => values.pop().unwrap(),
+            _ => Value::Array(values),
+        }))
+    }
+
+    fn encode(&self, _decoded: Decoded) -> Result<Vec<u8>, String> {
+        Err(String::from("Java serialized values are read-only"))
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn decode_object() {
+        // class Point implements Serializable { int x = 1; String label = "origin"; }
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[TC_OBJECT, TC_CLASSDESC, 0x00, 0x05]);
+        bytes.extend_from_slice(b"Point");
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&[SC_SERIALIZABLE, 0x00, 0x02]);
+        bytes.extend_from_slice(&[b'I', 0x00, 0x01, b'x']);
+        bytes.extend_from_slice(&[b'L', 0x00, 0x05]);
+        bytes.extend_from_slice(b"label");
+        bytes.extend_from_slice(&[TC_STRING, 0x00, 0x12]);
+        bytes.extend_from_slice(b"Ljava/lang/String;");
+        bytes.extend_from_slice(&[TC_ENDBLOCKDATA, TC_NULL]);
+        bytes.extend_from_slice(&1i32.to_be_bytes());
+        bytes.extend_from_slice(&[TC_STRING, 0x00, 0x06]);
+        bytes.extend_from_slice(b"origin");
+
+        assert!(JavaDecoder.detect(&bytes));
+        let view = JavaDecoder.decode(&bytes).unwrap().into_value().unwrap();
+        assert_eq!(
+            view,
+            json!({ "@class": "Point", "x": 1, "label": "origin" })
+        );
+
+        assert!(JavaDecoder.encode(Decoded::Value(view)).is_err());
+    }
+
+    fn reference(handle: i32) -> Vec<u8> {
+        let mut bytes = vec![TC_REFERENCE];
+        bytes.extend_from_slice(&(BASE_HANDLE + handle).to_be_bytes());
+        bytes
+    }
+
+    /// An `Object[]` of `size`, describing the class on first use and
+    /// referring to it as the first handle afterwards.
+    fn array_start(bytes: &mut Vec<u8>, first: bool, size: i32) {
+        bytes.push(TC_ARRAY);
+        if first {
+            bytes.extend_from_slice(&[TC_CLASSDESC, 0x00, 0x13]);
+            bytes.extend_from_slice(b"[Ljava.lang.Object;");
+            bytes.extend_from_slice(&[0; 8]);
+            bytes.extend_from_slice(&[SC_SERIALIZABLE, 0x00, 0x00]);
+            bytes.extend_from_slice(&[TC_ENDBLOCKDATA, TC_NULL]);
+        } else {
+            bytes.extend(reference(0));
+        }
+        bytes.extend_from_slice(&size.to_be_bytes());
+    }
+
+    #[test]
+    fn cyclic_reference() {
+        // class Node implements Serializable { Node next = this; }
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[TC_OBJECT, TC_CLASSDESC, 0x00, 0x04]);
+        bytes.extend_from_slice(b"Node");
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&[SC_SERIALIZABLE, 0x00, 0x01]);
+        bytes.extend_from_slice(&[b'L', 0x00, 0x04]);
+        bytes.extend_from_slice(b"next");
+        bytes.extend_from_slice(&[TC_STRING, 0x00, 0x06]);
+        bytes.extend_from_slice(b"LNode;");
+        bytes.extend_from_slice(&[TC_ENDBLOCKDATA, TC_NULL]);
+        bytes.extend(reference(2));
+
+        let view = JavaDecoder.decode(&bytes).unwrap().into_value().unwrap();
+        assert_eq!(view, json!({ "@class": "Node", "next": null }));
+    }
+
+    /// Arrays holding the previous one twice, each doubling the expansion.
+    fn doubling_arrays(count: i32) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        array_start(&mut bytes, true, 0);
+        for array in 1..count {
+            array_start(&mut bytes, false, 2);
+            bytes.extend(reference(array));
+            bytes.extend(reference(array));
+        }
+        bytes
+    }
+
+    #[test]
+    fn reference_expansion_limit() {
+        let view = JavaDecoder
+            .decode(&doubling_arrays(3))
+            .unwrap()
+            .into_value()
+            .unwrap();
+        assert_eq!(view, json!([[], [[], []], [[[], []], [[], []]]]));
+
+        assert!(JavaDecoder.decode(&doubling_arrays(64)).is_err());
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| {
+            let mut bytes = MAGIC.to_vec();
+            for level in 0..depth {
+                array_start(&mut bytes, level == 0, i32::from(level + 1 < depth));
+            }
+            bytes
+        };
+
+        assert!(JavaDecoder.decode(&nested(MAX_DEPTH)).is_ok());
+        assert!(JavaDecoder.decode(&nested(MAX_DEPTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use serde_json::Value;
+
+use super::{Decoded, Decoder, ValueFormat};
+
+pub struct JsonDecoder;
+
+impl Decoder for JsonDecoder {
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Json
+    }
+
+    /// Only objects and arrays are detected, bare numbers and strings are
+    /// left to be shown as text.
+    fn detect(&self, bytes: &[u8]) -> bool {
+        matches!(
+            bytes.iter().find(|b| !b.is_ascii_whitespace()),
+            Some(b'{') | Some(b'[')
+        ) && serde_json::from_slice::<Value>(bytes).is_ok()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+        serde_json::from_slice(bytes)
+            .map(Decoded::Value)
+            .map_err(|e| e.to_string())
+    }
+
+    fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(&decoded.into_value()?).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert!(JsonDecoder.detect(br#" {"a": [1, 2]}"#));
+        assert!(!JsonDecoder.detect(b"42"));
+        assert!(!JsonDecoder.detect(b"{not json"));
+    }
+}
@@ -0,0 +1,233 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{Map, Number, Value};
+
+use super::{too_deep_error, Decoded, Decoder, ValueFormat, MAX_DEPTH};
+
+const BINARY_KEY: &str = "$binary";
+const EXT_KEY: &str = "$ext";
+
+/// MessagePack maps and arrays. Binary and extension values have no JSON
+/// counterpart, so they are shown as `{"$binary": base64}` and
+/// `{"$ext": [type, base64]}` and written back as such. Values that would not
+/// be written back as the same bytes, such as maps with non-string keys or
+/// integers stored wider than needed, are read-only.
+pub struct MessagePackDecoder;
+
+fn read(bytes: &[u8]) -> Result<rmpv::Value, String> {
+    let mut remaining = bytes;
+    let value = rmpv::decode::read_value(&mut remaining).map_err(|e| e.to_string())?;
+
+    if !remaining.is_empty() {
+        return Err(String::from("Trailing bytes after MessagePack value"));
+    }
+
+    Ok(value)
+}
+
+/// Converts a value nested `depth` levels into the view.
+fn to_json(value: rmpv::Value, depth: usize) -> Result<Value, String> {
+    if matches!(value, rmpv::Value::Array(_) | rmpv::Value::Map(_)) && depth == MAX_DEPTH {
+        return Err(too_deep_error());
+    }
+
+    Ok(match value {
+        rmpv::Value::Nil => Value::Null,
+        rmpv::Value::Boolean(b) => Value::Bool(b),
+        rmpv::Value::Integer(i) => match i.as_i64() {
+            Some(n) => Value::from(n),
+            None => Value::from(i.as_u64().unwrap_or_default()),
+        },
+        rmpv::Value::F32(f) => float(f as f64)?,
+        rmpv::Value::F64(f) => float(f)?,
+        rmpv::Value::String(s) => Value::String(
+            s.into_str()
+                .ok_or_else(|| String::from("MessagePack string is not valid UTF-8"))?,
+        ),
+        rmpv::Value::Binary(b) => {
+            let mut map = Map::new();
+            map.insert(BINARY_KEY.to_string(), Value::String(BASE64.encode(b)));
+            Value::Object(map)
+        }
+        rmpv::Value::Ext(kind, data) => {
+            let mut map = Map::new();
+            map.insert(
+                EXT_KEY.to_string(),
+                Value::Array(vec![Value::from(kind), Value::String(BASE64.encode(data))]),
+            );
+            Value::Object(map)
+        }
+        rmpv::Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| to_json(item, depth + 1))
+                .collect::<Result<_, _>>()?,
+        ),
+        rmpv::Value::Map(entries) => {
+            let mut map = Map::new();
+            for (key, value) in entries {
+                let key = match to_json(key, depth + 1)? {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                map.insert(key, to_json(value, depth + 1)?);
+            }
+            Value::Object(map)
+        }
+    })
+}
+
+fn write(value: &rmpv::Value) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, value).map_err(|e| e.to_string())?;
+
+    Ok(bytes)
+}
+
+fn float(f: f64) -> Result<Value, String> {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .ok_or_else(|| format!("{} cannot be shown as JSON", f))
+}
+
+/// Converts a view nested `depth` levels into the value.
+fn from_json(value: Value, depth: usize) -> Result<rmpv::Value, String> {
+    if matches!(value, Value::Array(_) | Value::Object(_)) && depth == MAX_DEPTH {
+        return Err(too_deep_error());
+    }
+
+    Ok(match value {
+        Value::Null => rmpv::Value::Nil,
+        Value::Bool(b) => rmpv::Value::Boolean(b),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => rmpv::Value::from(i),
+            (_, Some(u)) => rmpv::Value::from(u),
+            _ => rmpv::Value::F64(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => rmpv::Value::from(s),
+        Value::Array(items) => rmpv::Value::Array(
+            items
+                .into_iter()
+                .map(|item| from_json(item, depth + 1))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => {
+            if map.len() == 1 {
+                if let Some(Value::String(data)) = map.get(BINARY_KEY) {
+                    return Ok(rmpv::Value::Binary(
+                        BASE64.decode(data).map_err(|e| e.to_string())?,
+                    ));
+                }
+
+                if let Some(Value::Array(ext)) = map.get(EXT_KEY) {
+                    if let [Value::Number(kind), Value::String(data)] = ext.as_slice() {
+                        let kind = kind
+                            .as_i64()
+                            .and_then(|k| i8::try_from(k).ok())
+                            .ok_or_else(|| String::from("Invalid extension type"))?;
+                        return Ok(rmpv::Value::Ext(
+                            kind,
+                            BASE64.decode(data).map_err(|e| e.to_string())?,
+                        ));
+                    }
+                }
+            }
+
+            rmpv::Value::Map(
+                map.into_iter()
+                    .map(|(key, value)| Ok((rmpv::Value::from(key), from_json(value, depth + 1)?)))
+                    .collect::<Result<_, String>>()?,
+            )
+        }
+    })
+}
+
+impl Decoder for MessagePackDecoder {
+    fn format(&self) -> ValueFormat {
+        ValueFormat::MessagePack
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        matches!(bytes.first(), Some(0x80..=0x9f) | Some(0xdc..=0xdf)) && read(bytes).is_ok()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+        let view = to_json(read(bytes)?, 0)?;
+
+        if write(&from_json(view.clone(), 0)?)? == bytes {
+            Ok(Decoded::Value(view))
+        } else {
+            Ok(Decoded::ReadOnly(view))
+        }
+    }
+
+    fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String> {
+        write(&from_json(decoded.into_value()?, 0)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn binary_round_trip() {
+        let value = rmpv::Value::Map(vec![
+            (rmpv::Value::from("id"), rmpv::Value::from(7)),
+            (rmpv::Value::from("blob"), rmpv::Value::Binary(vec![0, 255])),
+        ]);
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &value).unwrap();
+
+        assert!(MessagePackDecoder.detect(&bytes));
+        let view = MessagePackDecoder
+            .decode(&bytes)
+            .unwrap()
+            .into_value()
+            .unwrap();
+        assert_eq!(view, json!({ "id": 7, "blob": { "$binary": "AP8=" } }));
+
+        let encoded = MessagePackDecoder.encode(Decoded::Value(view)).unwrap();
+        assert_eq!(encoded, bytes);
+    }
+
+    #[test]
+    fn lossy_values_are_read_only() {
+        let lossy = [
+            rmpv::Value::Map(vec![(rmpv::Value::from(1), rmpv::Value::from("one"))]),
+            rmpv::Value::Array(vec![rmpv::Value::F32(0.5)]),
+        ];
+        for value in lossy {
+            let bytes = write(&value).unwrap();
+            assert!(matches!(
+                MessagePackDecoder.decode(&bytes).unwrap(),
+                Decoded::ReadOnly(_)
+            ));
+        }
+
+        // 7 stored as a uint32 rather than a fixint.
+        let wide = [0x91, 0xce, 0, 0, 0, 7];
+        assert!(matches!(
+            MessagePackDecoder.decode(&wide).unwrap(),
+            Decoded::ReadOnly(view) if view == json!([7])
+        ));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| {
+            let mut bytes = vec![0x91; depth];
+            bytes.push(0xc0);
+            bytes
+        };
+        assert!(MessagePackDecoder.decode(&nested(MAX_DEPTH)).is_ok());
+        assert!(MessagePackDecoder.decode(&nested(MAX_DEPTH + 1)).is_err());
+
+        let mut view = json!(null);
+        for _ in 0..=MAX_DEPTH {
+            view = json!([view]);
+        }
+        assert!(MessagePackDecoder.encode(Decoded::Value(view)).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use serde_json::{Map, Number, Value};
+
+use super::{too_deep_error, Decoded, Decoder, ValueFormat, MAX_DEPTH};
+
+const CLASS_KEY: &str = "__class";
+
+/// PHP `serialize()` output. Arrays with keys 0..n are shown as JSON arrays,
+/// other arrays as objects, and objects keep their class name under
+/// `__class`.
+pub struct PhpDecoder;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| String::from("Unexpected end of value"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        match self.next()? {
+            byte if byte == expected => Ok(()),
+            byte => Err(format!(
+                "Expected '{}' at {}, found '{}'",
+                expected as char,
+                self.pos - 1,
+                byte as char
+            )),
+        }
+    }
+
+    fn until(&mut self, end: u8) -> Result<&'a str, String> {
+        let start = self.pos;
+        while self.next()? != end {}
+
+        std::str::from_utf8(&self.bytes[start..self.pos - 1]).map_err(|e| e.to_string())
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, end: u8) -> Result<T, String> {
+        let text = self.until(end)?;
+        text.parse()
+            .map_err(|_| format!("Invalid number '{}'", text))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len: usize = self.number(b':')?;
+        self.expect(b'"')?;
+
+        let end = self.pos.saturating_add(len);
+        let text = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| String::from("Unexpected end of value"))?;
+        self.pos = end;
+
+        self.expect(b'"')?;
+        String::from_utf8(text.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn entries(&mut self, map: &mut Map<String, Value>) -> Result<bool, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(too_deep_error());
+        }
+        self.depth += 1;
+
+        let count: usize = self.number(b':')?;
+        self.expect(b'{')?;
+
+        let mut sequential = true;
+        for index in 0..count {
+            let key = match self.value()? {
+                Value::String(key) => {
+                    sequential = false;
+                    key
+                }
+                Value::Number(key) if key.is_i64() => {
+                    sequential &= key.as_i64() == Some(index as i64);
+                    key.to_string()
+                }
+                _ => return Err(String::from("Invalid array key")),
+            };
+            let value = self.value()?;
+            map.insert(key, value);
+        }
+
+        self.expect(b'}')?;
+        self.depth -= 1;
+        Ok(sequential)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let tag = self.next()?;
+
+        if tag == b'N' {
+            self.expect(b';')?;
+            return Ok(Value::Null);
+        }
+
+        self.expect(b':')?;
+
+        Ok(match tag {
+            b'b' => Value::Bool(self.number::<u8>(b';')? != 0),
+            b'i' => Value::from(self.number::<i64>(b';')?),
+            b'd' => {
+                let f: f64 = self.number(b';')?;
+                Value::Number(
+                    Number::from_f64(f).ok_or_else(|| format!("{} cannot be shown as JSON", f))?,
+                )
+            }
+            b's' => {
+                let text = self.string()?;
+                self.expect(b';')?;
+                Value::String(text)
+            }
+            b'a' => {
+                let mut map = Map::new();
+                if self.entries(&mut map)? {
+                    Value::Array(map.into_iter().map(|(_, value)| value).collect())
+                } else {
+                    Value::Object(map)
+                }
+            }
+            b'O' => {
+                let class = self.string()?;
+                self.expect(b':')?;
+
+                let mut map = Map::new();
+                map.insert(CLASS_KEY.to_string(), Value::String(class));
+                self.entries(&mut map)?;
+                Value::Object(map)
+            }
+            tag => return Err(format!("Unsupported type '{}'", tag as char)),
+        })
+    }
+}
+
+fn parse(bytes: &[u8]) -> Result<Value, String> {
+    let mut parser = Parser {
+        bytes,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+
+    if parser.pos != bytes.len() {
+        return Err(String::from("Trailing bytes after serialized value"));
+    }
+
+    Ok(value)
+}
+
+fn write_string(text: &str, out: &mut String) {
+    out.push_str(&format!("s:{}:\"{}\";", text.len(), text));
+}
+
+fn write_key(key: &str, out: &mut String) {
+    match key.parse::<i64>() {
+        Ok(index) if index.to_string() == key => out.push_str(&format!("i:{};", index)),
+        _ => write_string(key, out),
+    }
+}
+
+fn write(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("N;"),
+        Value::Bool(b) => out.push_str(&format!("b:{};", *b as u8)),
+        Value::Number(n) if n.is_f64() => out.push_str(&format!("d:{};", n)),
+        Value::Number(n) => out.push_str(&format!("i:{};", n)),
+        Value::String(text) => write_string(text, out),
+        Value::Array(items) => {
+            out.push_str(&format!("a:{}:{{", items.len()));
+            for (index, item) in items.iter().enumerate() {
+                out.push_str(&format!("i:{};", index));
+                write(item, out);
+            }
+            out.push('}');
+        }
+        Value::Object(map) => {
+            let class = match map.get(CLASS_KEY) {
+                Some(Value::String(class)) => Some(class),
+                _ => None,
+            };
+
+            match class {
+                Some(class) => out.push_str(&format!(
+                    "O:{}:\"{}\":{}:{{",
+                    class.len(),
+                    class,
+                    map.len() - 1
+                )),
+                None => out.push_str(&format!("a:{}:{{", map.len())),
+            }
+
+            for (key, value) in map {
+                if class.is_some() && key == CLASS_KEY {
+                    continue;
+                }
+
+                if class.is_some() {
+                    write_string(key, out);
+                } else {
+                    write_key(key, out);
+                }
+                write(value, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+impl Decoder for PhpDecoder {
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Php
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        matches!(
+            bytes,
+            [b'a' | b'O' | b's' | b'i' | b'd' | b'b', b':', ..] | [b'N', b';']
+        ) && parse(bytes).is_ok()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+        parse(bytes).map(Decoded::Value)
+    }
+
+    fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String> {
+        let mut out = String::new();
+        write(&decoded.into_value()?, &mut out);
+        Ok(out.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let serialized = concat!(
+            r#"a:3:{s:4:"name";s:5:"setui";s:4:"tags";a:2:{i:0;s:5:"redis";i:1;s:3:"gui";}"#,
+            r#"s:5:"owner";O:4:"User":2:{s:2:"id";i:7;s:5:"score";d:1.5;}}"#
+        );
+        assert!(PhpDecoder.detect(serialized.as_bytes()));
+
+        let view = PhpDecoder
+            .decode(serialized.as_bytes())
+            .unwrap()
+            .into_value()
+            .unwrap();
+        assert_eq!(
+            view,
+            json!({
+                "name": "setui",
+                "tags": ["redis", "gui"],
+                "owner": { "__class": "User", "id": 7, "score": 1.5 }
+            })
+        );
+
+        let encoded = PhpDecoder.encode(Decoded::Value(view)).unwrap();
+        assert_eq!(encoded, serialized.as_bytes());
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| "a:1:{i:0;".repeat(depth) + "N;" + &"}".repeat(depth);
+        assert!(parse(nested(MAX_DEPTH).as_bytes()).is_ok());
+        assert!(parse(nested(MAX_DEPTH + 1).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn multibyte_strings() {
+        let serialized = "s:6:\"h\u{e9}llo\";";
+        let view = PhpDecoder
+            .decode(serialized.as_bytes())
+            .unwrap()
+            .into_value()
+            .unwrap();
+        assert_eq!(view, json!("h\u{e9}llo"));
+    }
+}
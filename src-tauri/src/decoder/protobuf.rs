@@ -0,0 +1,239 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use super::{too_deep_error, Decoded, Decoder, ValueFormat, MAX_DEPTH};
+
+/// Protobuf without a schema: the wire format is shown field by field, with
+/// length-delimited fields read as text, a nested message or raw bytes.
+pub struct ProtobufDecoder;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+enum WireValue {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    String(String),
+    Bytes(String),
+    Message(Vec<WireField>),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct WireField {
+    field: u32,
+    #[serde(flatten)]
+    value: WireValue,
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| String::from("Truncated varint"))?;
+        *bytes = rest;
+
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(String::from("Varint is too long"))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if bytes.len() < len {
+        return Err(String::from("Truncated field"));
+    }
+
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn is_text(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes)
+        .map(|text| {
+            text.chars()
+                .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        })
+        .unwrap_or(false)
+}
+
+/// Parses the fields of a message nested `depth` levels into the value.
+fn parse(mut bytes: &[u8], depth: usize) -> Result<Vec<WireField>, String> {
+    let mut fields = Vec::new();
+
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        let field = u32::try_from(key >> 3).map_err(|_| String::from("Invalid field number"))?;
+        if field == 0 {
+            return Err(String::from("Invalid field number"));
+        }
+
+        let value = match key & 0x07 {
+            0 => WireValue::Varint(read_varint(&mut bytes)?),
+            1 => {
+                let data = take(&mut bytes, 8)?;
+                WireValue::Fixed64(u64::from_le_bytes(data.try_into().unwrap()))
+            }
+            2 => {
+                let len = read_varint(&mut bytes)? as usize;
+                let data = take(&mut bytes, len)?;
+
+                if is_text(data) {
+                    WireValue::String(String::from_utf8(data.to_vec()).unwrap())
+                } else if depth == MAX_DEPTH {
+                    return Err(too_deep_error());
+                } else {
+                    match parse(data, depth + 1) {
+                        Ok(nested) if !nested.is_empty() && write(&nested)? == data => {
+                            WireValue::Message(nested)
+                        }
+                        Err(e) if e == too_deep_error() => return Err(e),
+                        _ => WireValue::Bytes(BASE64.encode(data)),
+                    }
+                }
+            }
+            5 => {
+                let data = take(&mut bytes, 4)?;
+                WireValue::Fixed32(u32::from_le_bytes(data.try_into().unwrap()))
+            }
+            wire_type => return Err(format!("Unsupported wire type {}", wire_type)),
+        };
+
+        fields.push(WireField { field, value });
+    }
+
+    Ok(fields)
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write(fields: &[WireField]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+
+    for WireField { field, value } in fields {
+        let key = u64::from(*field) << 3;
+
+        match value {
+            WireValue::Varint(n) => {
+                write_varint(key, &mut out);
+                write_varint(*n, &mut out);
+            }
+            WireValue::Fixed64(n) => {
+                write_varint(key | 1, &mut out);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            WireValue::Fixed32(n) => {
+                write_varint(key | 5, &mut out);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            WireValue::String(_) | WireValue::Bytes(_) | WireValue::Message(_) => {
+                let data = match value {
+                    WireValue::String(text) => text.as_bytes().to_vec(),
+                    WireValue::Bytes(data) => BASE64.decode(data).map_err(|e| e.to_string())?,
+                    WireValue::Message(nested) => write(nested)?,
+                    _ => unreachable!(),
+                };
+
+                write_varint(key | 2, &mut out);
+                write_varint(data.len() as u64, &mut out);
+                out.extend_from_slice(&data);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+impl Decoder for ProtobufDecoder {
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Protobuf
+    }
+
+    /// Almost any byte string parses as some protobuf, so text is never
+    /// detected and the parsed fields have to re-encode to the same bytes.
+    fn detect(&self, bytes: &[u8]) -> bool {
+        !is_text(bytes)
+            && matches!(parse(bytes, 0), Ok(fields) if !fields.is_empty() && write(&fields).as_deref() == Ok(bytes))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+        let fields = parse(bytes, 0)?;
+        serde_json::to_value(fields)
+            .map(Decoded::Value)
+            .map_err(|e| e.to_string())
+    }
+
+    fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String> {
+        let fields: Vec<WireField> =
+            serde_json::from_value(decoded.into_value()?).map_err(|e| e.to_string())?;
+        write(&fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        // field 1: varint 150, field 2: "foo", field 3: { field 1: 1 }
+        let bytes = [
+            0x08, 0x96, 0x01, 0x12, 0x03, b'f', b'o', b'o', 0x1a, 0x02, 0x08, 0x01,
+        ];
+        assert!(ProtobufDecoder.detect(&bytes));
+
+        let view = ProtobufDecoder
+            .decode(&bytes)
+            .unwrap()
+            .into_value()
+            .unwrap();
+        assert_eq!(
+            view,
+            json!([
+                { "field": 1, "type": "varint", "value": 150 },
+                { "field": 2, "type": "string", "value": "foo" },
+                { "field": 3, "type": "message", "value": [
+                    { "field": 1, "type": "varint", "value": 1 }
+                ] }
+            ])
+        );
+
+        let encoded = ProtobufDecoder.encode(Decoded::Value(view)).unwrap();
+        assert_eq!(encoded, bytes);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| {
+            let mut bytes = vec![0x08, 0x01];
+            for _ in 0..depth {
+                let mut outer = vec![0x0a];
+                write_varint(bytes.len() as u64, &mut outer);
+                outer.extend_from_slice(&bytes);
+                bytes = outer;
+            }
+            bytes
+        };
+
+        assert!(ProtobufDecoder.decode(&nested(MAX_DEPTH)).is_ok());
+        assert!(ProtobufDecoder.decode(&nested(MAX_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn text_is_not_detected() {
+        assert!(!ProtobufDecoder.detect(b"hello world"));
+    }
+}
@@ -0,0 +1,270 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use prost_reflect::prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, SerializeOptions};
+use std::path::Path;
+use std::process::Command;
+
+use super::{Decoded, Decoder, ValueFormat};
+use crate::ob::{ProtoMapping, ProtoSchema};
+
+/// Protobuf decoded with a user-supplied message type, replacing the
+/// schemaless decoder for keys that have a mapping.
+pub struct SchemaDecoder {
+    message: MessageDescriptor,
+}
+
+impl SchemaDecoder {
+    /// Returns a decoder for the first mapping whose pattern matches `key`.
+    pub fn for_key(schemas: &[ProtoSchema], key: &str) -> Result<Option<Self>, String> {
+        for schema in schemas {
+            if let Some(mapping) = schema
+                .mappings
+                .iter()
+                .find(|m| matches_pattern(m.pattern.as_bytes(), key.as_bytes()))
+            {
+                let pool = load_pool(&schema.descriptor_set)?;
+                let message = find_message(&pool, &mapping.message_type)?;
+                return Ok(Some(SchemaDecoder { message }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn find_message(pool: &DescriptorPool, name: &str) -> Result<MessageDescriptor, String> {
+    pool.get_message_by_name(name.trim_start_matches('.'))
+        .ok_or_else(|| format!("Message type {} not found in schema", name))
+}
+
+pub fn load_pool(descriptor_set: &str) -> Result<DescriptorPool, String> {
+    let bytes = BASE64.decode(descriptor_set).map_err(|e| e.to_string())?;
+    DescriptorPool::decode(bytes.as_slice()).map_err(|e| e.to_string())
+}
+
+/// Checks that the descriptor set loads and every mapping names a message in
+/// it.
+pub fn validate_schema(descriptor_set: &str, mappings: &[ProtoMapping]) -> Result<(), String> {
+    let pool = load_pool(descriptor_set)?;
+
+    for mapping in mappings {
+        find_message(&pool, &mapping.message_type)?;
+    }
+
+    Ok(())
+}
+
+/// Reads a compiled FileDescriptorSet, or compiles `.proto` files with
+/// `protoc` (taken from `PROTOC` or the PATH). Returns it base64 encoded.
+pub fn load_descriptor_set(paths: &[String]) -> Result<String, String> {
+    let protos: Vec<&Path> = paths
+        .iter()
+        .map(Path::new)
+        .filter(|path| path.extension().is_some_and(|ext| ext == "proto"))
+        .collect();
+
+    let bytes = match (protos.len(), paths) {
+        (0, [path]) => std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?,
+        (0, _) => return Err(String::from("Expected a single descriptor set file")),
+        (n, _) if n == paths.len() => compile_protos(&protos)?,
+        _ => return Err(String::from("Cannot mix .proto files and descriptor sets")),
+    };
+
+    DescriptorPool::decode(bytes.as_slice()).map_err(|e| e.to_string())?;
+
+    Ok(BASE64.encode(bytes))
+}
+
+fn compile_protos(protos: &[&Path]) -> Result<Vec<u8>, String> {
+    // Removed when dropped, whether or not protoc succeeds.
+    let output = tempfile::Builder::new()
+        .prefix("setui-")
+        .suffix(".desc")
+        .tempfile()
+        .map_err(|e| e.to_string())?;
+    let protoc = std::env::var("PROTOC").unwrap_or_else(|_| String::from("protoc"));
+
+    let mut command = Command::new(&protoc);
+    command
+        .arg("--include_imports")
+        .arg(format!("--descriptor_set_out={}", output.path().display()));
+    for proto in protos {
+        if let Some(parent) = proto.parent().filter(|p| !p.as_os_str().is_empty()) {
+            command.arg("-I").arg(parent);
+        }
+    }
+    command.args(protos);
+
+    let result = command
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", protoc, e))?;
+    if !result.status.success() {
+        return Err(String::from_utf8_lossy(&result.stderr).trim().to_string());
+    }
+
+    std::fs::read(output.path()).map_err(|e| e.to_string())
+}
+
+/// Redis glob matching: `*`, `?`, `[abc]`, `[a-z]`, `[^a]` and `\` escapes.
+fn matches_pattern(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| matches_pattern(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && matches_pattern(rest, &text[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, text_rest)) = text.split_first() else {
+                return false;
+            };
+
+            let (negate, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+
+            let mut matched = false;
+            loop {
+                match class {
+                    [] => return false,
+                    [b']', tail @ ..] => {
+                        class = tail;
+                        break;
+                    }
+                    [b'\\', x, tail @ ..] => {
+                        matched |= *x == c;
+                        class = tail;
+                    }
+                    [lo, b'-', hi, tail @ ..] if *hi != b']' => {
+                        matched |= (*lo.min(hi)..=*lo.max(hi)).contains(&c);
+                        class = tail;
+                    }
+                    [x, tail @ ..] => {
+                        matched |= *x == c;
+                        class = tail;
+                    }
+                }
+            }
+
+            matched != negate && matches_pattern(class, text_rest)
+        }
+        Some((b'\\', [x, rest @ ..])) => {
+            text.first() == Some(x) && matches_pattern(rest, &text[1..])
+        }
+        Some((x, rest)) => text.first() == Some(x) && matches_pattern(rest, &text[1..]),
+    }
+}
+
+impl Decoder for SchemaDecoder {
+    fn format(&self) -> ValueFormat {
+        ValueFormat::Protobuf
+    }
+
+    fn detect(&self, bytes: &[u8]) -> bool {
+        DynamicMessage::decode(self.message.clone(), bytes).is_ok()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Decoded, String> {
+        let message =
+            DynamicMessage::decode(self.message.clone(), bytes).map_err(|e| e.to_string())?;
+
+        let options = SerializeOptions::new().skip_default_fields(false);
+        message
+            .serialize_with_options(serde_json::value::Serializer, &options)
+            .map(Decoded::Value)
+            .map_err(|e| e.to_string())
+    }
+
+    fn encode(&self, decoded: Decoded) -> Result<Vec<u8>, String> {
+        let message = DynamicMessage::deserialize(self.message.clone(), decoded.into_value()?)
+            .map_err(|e| e.to_string())?;
+
+        Ok(message.encode_to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+    use prost_reflect::prost_types::{
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    };
+    use serde_json::json;
+
+    fn user_schema() -> ProtoSchema {
+        let field = |name: &str, number: i32, kind: Type| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(kind as i32),
+            json_name: Some(name.to_string()),
+            ..Default::default()
+        };
+
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("user.proto".to_string()),
+                package: Some("app".to_string()),
+                syntax: Some("proto3".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("User".to_string()),
+                    field: vec![field("id", 1, Type::Int32), field("name", 2, Type::String)],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        ProtoSchema {
+            id: 1,
+            connection_id: 1,
+            name: "users".to_string(),
+            descriptor_set: BASE64.encode(set.encode_to_vec()),
+            mappings: vec![ProtoMapping {
+                pattern: "user:*".to_string(),
+                message_type: "app.User".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn decode_mapped_key() {
+        let schemas = vec![user_schema()];
+        assert!(SchemaDecoder::for_key(&schemas, "order:1")
+            .unwrap()
+            .is_none());
+
+        let decoder = SchemaDecoder::for_key(&schemas, "user:1").unwrap().unwrap();
+        let bytes = [0x08, 0x07, 0x12, 0x03, b'b', b'o', b'b'];
+
+        let view = decoder.decode(&bytes).unwrap().into_value().unwrap();
+        assert_eq!(view, json!({ "id": 7, "name": "bob" }));
+
+        let encoded = decoder
+            .encode(Decoded::Value(json!({ "id": 7, "name": "bob" })))
+            .unwrap();
+        assert_eq!(encoded, bytes);
+    }
+
+    #[test]
+    fn validate_mappings() {
+        let schema = user_schema();
+        assert!(validate_schema(&schema.descriptor_set, &schema.mappings).is_ok());
+
+        let mappings = vec![ProtoMapping {
+            pattern: "*".to_string(),
+            message_type: "app.Missing".to_string(),
+        }];
+        assert!(validate_schema(&schema.descriptor_set, &mappings).is_err());
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(matches_pattern(b"user:*", b"user:42"));
+        assert!(matches_pattern(b"h?llo", b"hello"));
+        assert!(matches_pattern(b"h[a-e]llo", b"hello"));
+        assert!(!matches_pattern(b"h[^e]llo", b"hello"));
+        assert!(matches_pattern(b"a\\*b", b"a*b"));
+        assert!(!matches_pattern(b"user:*", b"order:1"));
+    }
+}
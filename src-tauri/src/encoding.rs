@@ -0,0 +1,153 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use redis::{FromRedisValue, RedisResult, RedisWrite, ToRedisArgs};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueEncoding {
+    #[default]
+    Utf8,
+    Hex,
+    Base64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncodedValue {
+    encoding: ValueEncoding,
+    data: String,
+}
+
+/// Raw bytes of a Redis value, sent to the frontend as `{ encoding, data }`.
+/// Values read from Redis are tagged utf8 when they are valid UTF-8 and base64
+/// otherwise; values coming from the frontend keep the encoding they were sent
+/// with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "EncodedValue", into = "EncodedValue")]
+pub struct RedisValue {
+    pub bytes: Vec<u8>,
+    pub encoding: ValueEncoding,
+}
+
+impl RedisValue {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let encoding = if std::str::from_utf8(&bytes).is_ok() {
+            ValueEncoding::Utf8
+        } else {
+            ValueEncoding::Base64
+        };
+
+        RedisValue { bytes, encoding }
+    }
+
+    pub fn decode(encoding: ValueEncoding, data: &str) -> Result<Self, String> {
+        let bytes = match encoding {
+            ValueEncoding::Utf8 => data.as_bytes().to_vec(),
+            ValueEncoding::Hex => hex::decode(data).map_err(|e| e.to_string())?,
+            ValueEncoding::Base64 => BASE64.decode(data).map_err(|e| e.to_string())?,
+        };
+
+        Ok(RedisValue { bytes, encoding })
+    }
+
+    pub fn encode(&self) -> String {
+        match self.encoding {
+            ValueEncoding::Utf8 => String::from_utf8_lossy(&self.bytes).into_owned(),
+            ValueEncoding::Hex => hex::encode(&self.bytes),
+            ValueEncoding::Base64 => BASE64.encode(&self.bytes),
+        }
+    }
+
+    /// Switches the representation, falling back to base64 when utf8 is asked
+    /// for bytes that are not valid UTF-8.
+    pub fn with_encoding(mut self, encoding: ValueEncoding) -> Self {
+        self.encoding = match encoding {
+            ValueEncoding::Utf8 if std::str::from_utf8(&self.bytes).is_err() => {
+                ValueEncoding::Base64
+            }
+            encoding => encoding,
+        };
+        self
+    }
+}
+
+impl From<&str> for RedisValue {
+    fn from(value: &str) -> Self {
+        RedisValue {
+            bytes: value.as_bytes().to_vec(),
+            encoding: ValueEncoding::Utf8,
+        }
+    }
+}
+
+impl TryFrom<EncodedValue> for RedisValue {
+    type Error = String;
+
+    fn try_from(value: EncodedValue) -> Result<Self, Self::Error> {
+        RedisValue::decode(value.encoding, &value.data)
+    }
+}
+
+impl From<RedisValue> for EncodedValue {
+    fn from(value: RedisValue) -> Self {
+        EncodedValue {
+            encoding: value.encoding,
+            data: value.encode(),
+        }
+    }
+}
+
+impl FromRedisValue for RedisValue {
+    fn from_redis_value(v: &redis::Value) -> RedisResult<Self> {
+        let bytes: Vec<u8> = FromRedisValue::from_redis_value(v)?;
+        Ok(RedisValue::from_bytes(bytes))
+    }
+}
+
+impl ToRedisArgs for RedisValue {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        out.write_arg(&self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_encoding() {
+        let value = RedisValue::from_bytes(b"hello".to_vec());
+        assert_eq!(value.encoding, ValueEncoding::Utf8);
+        assert_eq!(value.encode(), "hello");
+
+        let value = RedisValue::from_bytes(vec![0xff, 0x00, 0x81]);
+        assert_eq!(value.encoding, ValueEncoding::Base64);
+        assert_eq!(value.encode(), "/wCB");
+    }
+
+    #[test]
+    fn decode_encodings() {
+        let bytes = vec![0xde, 0xad, 0xbe, 0xef];
+
+        let value = RedisValue::decode(ValueEncoding::Hex, "deadbeef").unwrap();
+        assert_eq!(value.bytes, bytes);
+
+        let value = RedisValue::decode(ValueEncoding::Base64, "3q2+7w==").unwrap();
+        assert_eq!(value.bytes, bytes);
+
+        assert!(RedisValue::decode(ValueEncoding::Hex, "xyz").is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let value: RedisValue =
+            serde_json::from_str(r#"{"encoding":"hex","data":"00ff"}"#).unwrap();
+        assert_eq!(value.bytes, vec![0x00, 0xff]);
+
+        let json = serde_json::to_string(&value.with_encoding(ValueEncoding::Utf8)).unwrap();
+        assert_eq!(json, r#"{"encoding":"base64","data":"AP8="}"#);
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a confirmation token stays valid.
+const CONFIRMATION_TTL: Duration = Duration::from_secs(60);
+
+/// Commands that need a confirmation token unless a connection sets its own
+/// list. Entries are a command name optionally followed by a subcommand.
+/// `DEL` and `UNLINK` only count when a key looks like a glob pattern, since
+/// those are the ones that tend to be meant as "delete everything matching".
+pub const DEFAULT_GUARDED_COMMANDS: [&str; 7] = [
+    "FLUSHALL",
+    "FLUSHDB",
+    "KEYS",
+    "DEL",
+    "UNLINK",
+    "CONFIG SET",
+    "SHUTDOWN",
+];
+
+pub fn default_guarded_commands() -> Vec<String> {
+    DEFAULT_GUARDED_COMMANDS
+        .iter()
+        .map(|command| command.to_string())
+        .collect()
+}
+
+/// The entry of `guarded` that `args` falls under, if any.
+pub fn guarded_by<'a>(guarded: &'a [String], args: &[Vec<u8>]) -> Option<&'a str> {
+    guarded
+        .iter()
+        .find(|rule| {
+            let words: Vec<&str> = rule.split_whitespace().collect();
+            if words.is_empty() || words.len() > args.len() {
+                return false;
+            }
+
+            let matches = words
+                .iter()
+                .zip(args)
+                .all(|(word, arg)| arg.eq_ignore_ascii_case(word.as_bytes()));
+
+            match words.as_slice() {
+                [name]
+                    if name.eq_ignore_ascii_case("DEL") || name.eq_ignore_ascii_case("UNLINK") =>
+                {
+                    matches && args[1..].iter().any(|key| is_pattern(key))
+                }
+                _ => matches,
+            }
+        })
+        .map(String::as_str)
+}
+
+fn is_pattern(key: &[u8]) -> bool {
+    key.iter().any(|c| matches!(c, b'*' | b'?' | b'['))
+}
+
+struct PendingConfirmation {
+    connection_id: i64,
+    args: Vec<Vec<u8>>,
+    issued: Instant,
+}
+
+/// Single-use tokens that let one guarded command run once on one connection.
+#[derive(Default)]
+pub struct ConfirmationRegistry {
+    pending: Mutex<HashMap<String, PendingConfirmation>>,
+}
+
+impl ConfirmationRegistry {
+    pub fn new() -> Self {
+        ConfirmationRegistry {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn issue(&self, connection_id: i64, args: &[Vec<u8>]) -> String {
+        let token = uuid::Uuid::new_v4().to_string();
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| p.issued.elapsed() < CONFIRMATION_TTL);
+        pending.insert(
+            token.clone(),
+            PendingConfirmation {
+                connection_id,
+                args: args.to_vec(),
+                issued: Instant::now(),
+            },
+        );
+
+        token
+    }
+
+    /// Uses up `token`, returning whether it was issued for exactly this
+    /// command on this connection and has not expired.
+    pub fn consume(&self, token: &str, connection_id: i64, args: &[Vec<u8>]) -> bool {
+        match self.pending.lock().unwrap().remove(token) {
+            Some(p) => {
+                p.connection_id == connection_id
+                    && p.args == args
+                    && p.issued.elapsed() < CONFIRMATION_TTL
+            }
+            None => false,
+        }
+    }
+
+    /// Drops every token issued for `connection_id`.
+    pub fn revoke(&self, connection_id: i64) {
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|_, p| p.connection_id != connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::tokenize;
+
+    #[test]
+    fn default_rules() {
+        let guarded = default_guarded_commands();
+        let rule = |line: &str| guarded_by(&guarded, &tokenize(line).unwrap());
+
+        assert_eq!(rule("flushall async"), Some("FLUSHALL"));
+        assert_eq!(rule("config set maxmemory 0"), Some("CONFIG SET"));
+        assert_eq!(rule("CONFIG GET maxmemory"), None);
+        assert_eq!(rule("DEL user:1 user:2"), None);
+        assert_eq!(rule("DEL user:*"), Some("DEL"));
+        assert_eq!(rule("unlink user:1"), None);
+        assert_eq!(rule("UNLINK user:1 session:?"), Some("UNLINK"));
+        assert_eq!(rule("GET user:*"), None);
+    }
+
+    #[test]
+    fn single_use_tokens() {
+        let registry = ConfirmationRegistry::new();
+        let args = tokenize("FLUSHDB").unwrap();
+
+        let token = registry.issue(1, &args);
+        assert!(!registry.consume(&token, 2, &args));
+
+        let token = registry.issue(1, &args);
+        assert!(!registry.consume(&token, 1, &tokenize("FLUSHALL").unwrap()));
+
+        let token = registry.issue(1, &args);
+        assert!(registry.consume(&token, 1, &args));
+        assert!(!registry.consume(&token, 1, &args));
+
+        let token = registry.issue(1, &args);
+        let other = registry.issue(2, &args);
+        registry.revoke(1);
+        assert!(!registry.consume(&token, 1, &args));
+        assert!(registry.consume(&other, 2, &args));
+    }
+}
@@ -0,0 +1,104 @@
+use rusqlite::{params, Result as SqliteResult};
+
+use crate::ob::HistoryEntry;
+use crate::sqlite::{Entity, SqliteManager};
+
+/// Deletes the history of connection `connection_id` beyond the newest
+/// `max_entries` and recorded more than `max_age_secs` before `now`, in Unix
+/// milliseconds. Returns how many entries were removed.
+pub fn prune(
+    manager: &SqliteManager,
+    connection_id: i64,
+    max_entries: Option<u32>,
+    max_age_secs: Option<u64>,
+    now: i64,
+) -> SqliteResult<usize> {
+    let mut deleted = 0;
+
+    if let Some(max_entries) = max_entries {
+        deleted += manager.delete_where::<HistoryEntry>(
+            &format!(
+                "json_extract(data, '$.connection_id') = ?1 AND id NOT IN (
+                    SELECT id FROM {} WHERE json_extract(data, '$.connection_id') = ?1
+                    ORDER BY id DESC LIMIT ?2
+                )",
+                HistoryEntry::get_table_name()
+            ),
+            params![connection_id, max_entries],
+        )?;
+    }
+
+    if let Some(max_age_secs) = max_age_secs {
+        deleted += manager.delete_where::<HistoryEntry>(
+            "json_extract(data, '$.connection_id') = ? AND json_extract(data, '$.timestamp') < ?",
+            params![connection_id, cutoff(now, max_age_secs)],
+        )?;
+    }
+
+    Ok(deleted)
+}
+
+/// The time `max_age_secs` before `now`. Ages beyond what a timestamp can
+/// hold reach back to the earliest one instead of wrapping into the future.
+fn cutoff(now: i64, max_age_secs: u64) -> i64 {
+    let max_age = i64::try_from(max_age_secs.saturating_mul(1000)).unwrap_or(i64::MAX);
+    now.saturating_sub(max_age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+    use std::fs;
+
+    const TEST_DIR: &str = "./test_history_db";
+
+    fn entry(connection_id: i64, command: &str, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            connection_id,
+            command: command.to_string(),
+            timestamp,
+            duration_ms: 1,
+            success: true,
+            error: None,
+            composite: false,
+        }
+    }
+
+    fn commands(manager: &SqliteManager) -> Vec<String> {
+        manager
+            .list::<HistoryEntry>()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect()
+    }
+
+    #[test]
+    #[serial]
+    fn prune_entries() {
+        let _ = fs::remove_dir_all(TEST_DIR);
+        fs::create_dir_all(TEST_DIR).unwrap();
+        let manager = SqliteManager {
+            path: format!("{}/test.db", TEST_DIR),
+        };
+        manager.init_table::<HistoryEntry>().unwrap();
+
+        let now = 10_000_000;
+        manager.insert(&entry(1, "GET a", now - 9_000_000)).unwrap();
+        manager.insert(&entry(1, "GET b", now - 5_000)).unwrap();
+        manager.insert(&entry(1, "GET c", now - 1_000)).unwrap();
+        manager.insert(&entry(2, "GET d", now - 9_000_000)).unwrap();
+
+        // Far larger than any timestamp, so nothing is old enough.
+        assert_eq!(prune(&manager, 1, None, Some(u64::MAX), now).unwrap(), 0);
+        assert_eq!(prune(&manager, 1, None, Some(60), now).unwrap(), 1);
+        assert_eq!(commands(&manager), vec!["GET b", "GET c", "GET d"]);
+
+        assert_eq!(prune(&manager, 1, Some(1), None, now).unwrap(), 1);
+        assert_eq!(commands(&manager), vec!["GET c", "GET d"]);
+
+        let _ = fs::remove_dir_all(TEST_DIR);
+    }
+}
@@ -1,45 +1,212 @@
-use std::sync::Mutex;
-
+use audit::{AuditFilter, ExportFormat};
+use console::RedisReply;
+use decoder::{DecodedValue, Decoder, DecoderRegistry, SchemaDecoder, ValueFormat};
+use encoding::{RedisValue, ValueEncoding};
+use guard::ConfirmationRegistry;
 use log::error;
-use ob::Connection;
-use redis::{RedisKeyValue, RedisManager};
+use ob::{AuditEntry, Connection, HistoryEntry, ProtoMapping, ProtoSchema, SshConfig};
+use operation::OperationRegistry;
+use redis::{
+    ConflictPolicy, DeleteEvent, KeySnapshot, ListEnd, RedisHashField, RedisKeyPage,
+    RedisKeyPayload, RedisKeyPreview, RedisKeyValue, RedisKeyspace, RedisListElement, RedisManager,
+    RedisSetMember, RedisStreamConsumer, RedisStreamGroup, RedisStreamPage,
+    RedisStreamPendingEntry, RedisTtlReport, RedisValuePage, RedisZsetMember, ScanEvent,
+    StreamTrim, TtlChange, TtlCondition,
+};
+use rusqlite::params;
 use sqlite::SqliteManager;
-use tauri::Manager;
+use std::future::Future;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::{ipc::Channel, Manager};
 
+mod audit;
+mod console;
+mod decoder;
+mod encoding;
+mod guard;
+mod history;
 mod ob;
+mod operation;
 mod redis;
+mod sentinel;
 mod sqlite;
+mod ssh;
+mod tls;
 
 #[derive(Default)]
 struct AppState {
     sqlite_manager: SqliteManager,
-    redis_manager: Mutex<RedisManager>,
+    redis_manager: RedisManager,
+    operations: OperationRegistry,
+    decoders: DecoderRegistry,
+    confirmations: ConfirmationRegistry,
 }
 
 fn init_database(sqlite_manager: &SqliteManager) -> Result<(), Box<dyn std::error::Error>> {
     sqlite_manager.init_table::<Connection>()?;
+    sqlite_manager.init_table::<ProtoSchema>()?;
+    sqlite_manager.init_table::<HistoryEntry>()?;
+    sqlite_manager.init_table::<AuditEntry>()?;
     Ok(())
 }
 
-#[tauri::command]
-fn create_connection(
-    app_handler: tauri::AppHandle,
-    uri: String,
-    name: String,
-    color: String,
-) -> Result<i64, String> {
-    let state = app_handler.state::<AppState>();
+fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
 
-    let connection = Connection {
+/// Saves `command` to the history of connection `id`. A history that cannot be
+/// written is logged and never fails the command itself.
+fn record_history(
+    state: &AppState,
+    id: i64,
+    command: String,
+    composite: bool,
+    started: Instant,
+    error: Option<String>,
+) {
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let entry = HistoryEntry {
         id: 0,
-        uri_connection: uri,
-        name,
-        color,
+        connection_id: id,
+        command,
+        timestamp: unix_millis() - duration_ms as i64,
+        duration_ms,
+        success: error.is_none(),
+        error,
+        composite,
+    };
+
+    if let Err(e) = state.sqlite_manager.insert(&entry) {
+        error!("Failed to record command history: {}", e);
+    }
+}
+
+/// Digests of `keys` taken before a mutation, for the audit log.
+async fn previous_digests(
+    state: &AppState,
+    id: i64,
+    keys: &[&[u8]],
+) -> Result<Vec<Option<String>>, String> {
+    let mut digests = Vec::with_capacity(keys.len());
+    for key in keys {
+        digests.push(
+            state
+                .redis_manager
+                .value_digest(id, key)
+                .await
+                .map_err(|e| e.to_string())?,
+        );
+    }
+    Ok(digests)
+}
+
+/// Appends a mutation of `keys` on connection `id` to the audit log, one
+/// entry per key or a single keyless entry. Failures are logged only.
+fn record_audit(
+    state: &AppState,
+    id: i64,
+    operation: &[u8],
+    keys: &[&[u8]],
+    digests: Vec<Option<String>>,
+) {
+    let connection_name = match state.sqlite_manager.get_by_id::<Connection>(id) {
+        Ok(Some(connection)) => connection.name,
+        _ => id.to_string(),
+    };
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+    let timestamp = unix_millis();
+
+    let keys: Vec<Option<&[u8]>> = match keys {
+        [] => vec![None],
+        keys => keys.iter().copied().map(Some).collect(),
+    };
+
+    for (key, previous_digest) in keys
+        .into_iter()
+        .zip(digests.into_iter().chain(std::iter::repeat(None)))
+    {
+        let entry = AuditEntry {
+            id: 0,
+            connection_id: id,
+            connection_name: connection_name.clone(),
+            user: user.clone(),
+            key: key.map(|key| String::from_utf8_lossy(key).into_owned()),
+            operation: String::from_utf8_lossy(operation).to_uppercase(),
+            previous_digest,
+            timestamp,
+        };
+
+        if let Err(e) = state.sqlite_manager.insert(&entry) {
+            error!("Failed to record audit entry: {}", e);
+        }
+    }
+}
+
+/// Runs an edit written as the command `args`, whose second argument is the
+/// key. It is recorded in the history of connection `id` and, when it
+/// succeeds, in the audit log. The key is dumped once, for both its digest
+/// and the undo snapshot the edit keeps.
+async fn run_edit<T>(
+    state: &AppState,
+    id: i64,
+    args: &[&[u8]],
+    action: impl Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    let command = console::format_command(args);
+    let keys = &args[1..args.len().min(2)];
+    let snapshot = match keys {
+        [key] => Some(
+            state
+                .redis_manager
+                .snapshot(id, &String::from_utf8_lossy(key))
+                .await
+                .map_err(|e| e.to_string())?,
+        ),
+        _ => None,
+    };
+    let digests = snapshot.iter().map(KeySnapshot::digest).collect();
+    let started = Instant::now();
+
+    let result = match snapshot {
+        Some(snapshot) => {
+            state
+                .redis_manager
+                .with_snapshot(id, snapshot, action)
+                .await
+        }
+        None => action.await,
     };
+    record_history(
+        state,
+        id,
+        command,
+        false,
+        started,
+        result.as_ref().err().cloned(),
+    );
+
+    if result.is_ok() {
+        record_audit(state, id, args[0], keys, digests);
+    }
+
+    result
+}
+
+/// Saves `entity` as a new connection and returns its id. The id it carries
+/// is ignored.
+#[tauri::command]
+fn create_connection(app_handler: tauri::AppHandle, entity: Connection) -> Result<i64, String> {
+    let state = app_handler.state::<AppState>();
 
     state
         .sqlite_manager
-        .insert(&connection)
+        .insert(&entity)
         .map_err(|e| e.to_string())
 }
 
@@ -60,13 +227,34 @@ fn update_connection(app_handler: tauri::AppHandle, entity: Connection) -> Resul
     state
         .sqlite_manager
         .update(&entity)
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+
+    state
+        .redis_manager
+        .set_read_only(entity.id, entity.is_read_only());
+    Ok(())
 }
 
 #[tauri::command]
 fn delete_connection(app_handler: tauri::AppHandle, id: i64) -> Result<(), String> {
     let state = app_handler.state::<AppState>();
 
+    let schemas = state
+        .sqlite_manager
+        .list::<ProtoSchema>()
+        .map_err(|e| e.to_string())?;
+    for schema in schemas.iter().filter(|schema| schema.connection_id == id) {
+        state
+            .sqlite_manager
+            .delete::<ProtoSchema>(schema.id)
+            .map_err(|e| e.to_string())?;
+    }
+
+    state
+        .sqlite_manager
+        .delete_where::<HistoryEntry>("json_extract(data, '$.connection_id') = ?", params![id])
+        .map_err(|e| e.to_string())?;
+
     state
         .sqlite_manager
         .delete::<Connection>(id)
@@ -81,89 +269,1429 @@ fn list_connection(app_handler: tauri::AppHandle) -> Result<Vec<Connection>, Str
 }
 
 #[tauri::command]
-fn connect_redis(app_handler: tauri::AppHandle, id: i64, uri: String) -> Result<(), String> {
+async fn connect_redis(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    uri: String,
+    db: Option<i64>,
+) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    let connection = state
+        .sqlite_manager
+        .get_by_id::<Connection>(id)
+        .map_err(|e| e.to_string())?;
+    state.redis_manager.set_read_only(
+        id,
+        connection
+            .as_ref()
+            .is_some_and(|connection| connection.is_read_only()),
+    );
+
+    let (tls, ssh, sentinel) = connection
+        .map(|connection| (connection.tls, connection.ssh, connection.sentinel))
+        .unwrap_or_default();
+    state
+        .redis_manager
+        .connect(id, &uri, db, tls.as_ref(), ssh.as_ref(), sentinel.as_ref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The host key fingerprint of the SSH server in `ssh`, shown to the user to
+/// confirm before it is saved in the connection's `host_key_fingerprint`.
+#[tauri::command]
+async fn get_ssh_host_key(ssh: SshConfig) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || ssh::host_key_fingerprint(&ssh))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Switches connection `id` to database `db`. Key commands on the connection
+/// work in the selected database until another one is selected. Pending
+/// confirmations are dropped, since they were given for the previous one.
+#[tauri::command]
+async fn select_redis_db(app_handler: tauri::AppHandle, id: i64, db: i64) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    let result = state.redis_manager.select_db(id, db).await;
+    state.confirmations.revoke(id);
+    result.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_redis_selected_db(app_handler: tauri::AppHandle, id: i64) -> Result<i64, String> {
+    let state = app_handler.state::<AppState>();
+
+    state
+        .redis_manager
+        .selected_db(id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_redis_keyspace(
+    app_handler: tauri::AppHandle,
+    id: i64,
+) -> Result<Vec<RedisKeyspace>, String> {
     let state = app_handler.state::<AppState>();
-    let mut redis_manager = state.redis_manager.lock().unwrap();
 
-    redis_manager.connect(id, &uri).map_err(|e| e.to_string())
+    state
+        .redis_manager
+        .keyspace(id)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 fn disconnect_redis(app_handler: tauri::AppHandle, id: i64) {
     let state = app_handler.state::<AppState>();
-    let mut redis_manager = state.redis_manager.lock().unwrap();
 
-    redis_manager.disconnect(id);
+    state.redis_manager.disconnect(id);
+    state.redis_manager.set_read_only(id, false);
 }
 
 #[tauri::command]
-fn get_redis_keys(
+async fn get_redis_keys(
     app_handler: tauri::AppHandle,
     id: i64,
     pattern: String,
+    operation_id: Option<String>,
 ) -> Result<Vec<String>, String> {
     let state = app_handler.state::<AppState>();
-    let redis_manager = state.redis_manager.lock().unwrap();
+    let operation = state.operations.start(operation_id);
+
+    state
+        .redis_manager
+        .get_keys(id, &pattern, operation.token())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn scan_redis_keys(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    pattern: String,
+    cursor: u64,
+    count: usize,
+) -> Result<RedisKeyPage, String> {
+    let state = app_handler.state::<AppState>();
 
-    redis_manager
-        .get_keys(id, &pattern)
+    state
+        .redis_manager
+        .scan_keys(id, &pattern, cursor, count)
+        .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn get_redis_key_info(
+fn stream_redis_keys(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    pattern: String,
+    count: usize,
+    operation_id: String,
+    on_event: Channel<ScanEvent>,
+) {
+    tauri::async_runtime::spawn(async move {
+        let state = app_handler.state::<AppState>();
+        let operation = state.operations.start(Some(operation_id));
+
+        let result = state
+            .redis_manager
+            .scan_keys_streaming(id, &pattern, count, operation.token(), |event| {
+                if let Err(e) = on_event.send(event) {
+                    error!("Failed to send scan event: {}", e);
+                }
+            })
+            .await;
+
+        if let Err(e) = result {
+            let _ = on_event.send(ScanEvent::Error {
+                message: e.to_string(),
+            });
+        }
+    });
+}
+
+/// A bulk delete preview, with the token `delete_redis_keys_by_pattern`
+/// needs to go ahead.
+#[derive(serde::Serialize)]
+struct BulkDeletePreview {
+    #[serde(flatten)]
+    preview: RedisKeyPreview,
+    confirmation: String,
+}
+
+fn bulk_delete_args(pattern: &str) -> Vec<Vec<u8>> {
+    vec![b"UNLINK".to_vec(), pattern.as_bytes().to_vec()]
+}
+
+#[tauri::command]
+async fn preview_redis_bulk_delete(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    pattern: String,
+    sample_size: usize,
+    operation_id: Option<String>,
+) -> Result<BulkDeletePreview, String> {
+    let state = app_handler.state::<AppState>();
+    let operation = state.operations.start(operation_id);
+
+    let preview = state
+        .redis_manager
+        .preview_pattern(id, &pattern, sample_size, operation.token())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(BulkDeletePreview {
+        preview,
+        confirmation: state.confirmations.issue(id, &bulk_delete_args(&pattern)),
+    })
+}
+
+#[tauri::command]
+fn delete_redis_keys_by_pattern(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    pattern: String,
+    batch_size: usize,
+    confirmation: String,
+    operation_id: String,
+    on_event: Channel<DeleteEvent>,
+) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    if !state
+        .confirmations
+        .consume(&confirmation, id, &bulk_delete_args(&pattern))
+    {
+        return Err(String::from("Bulk delete has not been confirmed"));
+    }
+
+    tauri::async_runtime::spawn(async move {
+        let state = app_handler.state::<AppState>();
+        let operation = state.operations.start(Some(operation_id));
+        let started = Instant::now();
+        let mut failure = None;
+
+        let deleted = state
+            .redis_manager
+            .delete_by_pattern(id, &pattern, batch_size, operation.token(), |event| {
+                if let DeleteEvent::Error { message, .. } = &event {
+                    failure = Some(message.clone());
+                }
+                if let Err(e) = on_event.send(event) {
+                    error!("Failed to send delete event: {}", e);
+                }
+            })
+            .await;
+
+        let args = bulk_delete_args(&pattern);
+        let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+        record_history(
+            &state,
+            id,
+            console::format_command(&args),
+            true,
+            started,
+            failure,
+        );
+
+        let keys: Vec<&[u8]> = deleted.iter().map(String::as_bytes).collect();
+        if !keys.is_empty() {
+            record_audit(&state, id, b"UNLINK", &keys, Vec::new());
+        }
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_redis_key_info(
     app_handler: tauri::AppHandle,
     id: i64,
     key: String,
+    encoding: Option<ValueEncoding>,
 ) -> Result<Option<RedisKeyValue>, String> {
     let state = app_handler.state::<AppState>();
-    let redis_manager = state.redis_manager.lock().unwrap();
 
-    redis_manager
+    let info = state
+        .redis_manager
         .get_key_info(id, &key)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(info.map(|info| match encoding {
+        Some(encoding) if info.data_type == "string" => RedisKeyValue {
+            value: info.value.with_encoding(encoding),
+            ..info
+        },
+        _ => info,
+    }))
+}
+
+#[tauri::command]
+async fn get_redis_value_page(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    cursor: u64,
+    count: usize,
+) -> Result<Option<RedisValuePage>, String> {
+    let state = app_handler.state::<AppState>();
+
+    state
+        .redis_manager
+        .get_value_page(id, &key, cursor, count)
+        .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn set_redis_key(
+async fn get_redis_stream_range(
     app_handler: tauri::AppHandle,
     id: i64,
     key: String,
-    value: String,
-) -> Result<(), String> {
+    start: Option<String>,
+    count: usize,
+    reverse: bool,
+) -> Result<RedisStreamPage, String> {
+    let state = app_handler.state::<AppState>();
+
+    state
+        .redis_manager
+        .get_stream_range(id, &key, start.as_deref(), count, reverse)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_redis_stream_entry(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    entry_id: Option<String>,
+    fields: Vec<(RedisValue, RedisValue)>,
+) -> Result<String, String> {
+    let state = app_handler.state::<AppState>();
+
+    let mut xadd_args: Vec<&[u8]> = vec![b"XADD", key.as_bytes()];
+    xadd_args.push(entry_id.as_deref().unwrap_or("*").as_bytes());
+    for (field, value) in &fields {
+        xadd_args.push(&field.bytes);
+        xadd_args.push(&value.bytes);
+    }
+
+    run_edit(&state, id, &xadd_args, async {
+        state
+            .redis_manager
+            .add_stream_entry(id, &key, entry_id.as_deref(), &fields)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_redis_stream_entries(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    entry_ids: Vec<String>,
+) -> Result<u64, String> {
+    let state = app_handler.state::<AppState>();
+
+    let mut xdel_args: Vec<&[u8]> = vec![b"XDEL", key.as_bytes()];
+    xdel_args.extend(entry_ids.iter().map(|entry_id| entry_id.as_bytes()));
+
+    run_edit(&state, id, &xdel_args, async {
+        state
+            .redis_manager
+            .delete_stream_entries(id, &key, &entry_ids)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn trim_redis_stream(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    trim: StreamTrim,
+    approximate: bool,
+) -> Result<u64, String> {
+    let state = app_handler.state::<AppState>();
+
+    let (strategy, threshold): (&[u8], String) = match &trim {
+        StreamTrim::MaxLen(len) => (b"MAXLEN", len.to_string()),
+        StreamTrim::MinId(min_id) => (b"MINID", min_id.clone()),
+    };
+
+    run_edit(
+        &state,
+        id,
+        &[
+            b"XTRIM",
+            key.as_bytes(),
+            strategy,
+            if approximate { b"~" } else { b"=" },
+            threshold.as_bytes(),
+        ],
+        async {
+            state
+                .redis_manager
+                .trim_stream(id, &key, &trim, approximate)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn get_redis_stream_groups(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+) -> Result<Vec<RedisStreamGroup>, String> {
     let state = app_handler.state::<AppState>();
-    let redis_manager = state.redis_manager.lock().unwrap();
 
-    redis_manager
-        .set_key(id, &key, &value)
+    state
+        .redis_manager
+        .get_stream_groups(id, &key)
+        .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn delete_redis_key(app_handler: tauri::AppHandle, id: i64, key: String) -> Result<(), String> {
+async fn get_redis_stream_consumers(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    group: String,
+) -> Result<Vec<RedisStreamConsumer>, String> {
     let state = app_handler.state::<AppState>();
-    let redis_manager = state.redis_manager.lock().unwrap();
 
-    redis_manager
-        .delete_key(id, &key)
+    state
+        .redis_manager
+        .get_stream_consumers(id, &key, &group)
+        .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn set_redis_ttl(
+async fn get_redis_stream_pending(
     app_handler: tauri::AppHandle,
     id: i64,
     key: String,
-    ttl: i64,
-) -> Result<(), String> {
+    group: String,
+    start: Option<String>,
+    count: usize,
+    consumer: Option<String>,
+) -> Result<Vec<RedisStreamPendingEntry>, String> {
     let state = app_handler.state::<AppState>();
-    let redis_manager = state.redis_manager.lock().unwrap();
 
-    redis_manager
-        .set_ttl(id, &key, ttl)
+    state
+        .redis_manager
+        .get_stream_pending(
+            id,
+            &key,
+            &group,
+            start.as_deref(),
+            count,
+            consumer.as_deref(),
+        )
+        .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn set_redis_hash_field(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    field: RedisValue,
+    value: RedisValue,
+) -> Result<RedisHashField, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[b"HSET", key.as_bytes(), &field.bytes, &value.bytes],
+        async {
+            state
+                .redis_manager
+                .set_hash_field(id, &key, &field, &value)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn delete_redis_hash_field(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    field: RedisValue,
+) -> Result<RedisHashField, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[b"HDEL", key.as_bytes(), &field.bytes],
+        async {
+            state
+                .redis_manager
+                .delete_hash_field(id, &key, &field)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn set_redis_list_element(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    index: i64,
+    value: RedisValue,
+) -> Result<RedisListElement, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[
+            b"LSET",
+            key.as_bytes(),
+            index.to_string().as_bytes(),
+            &value.bytes,
+        ],
+        async {
+            state
+                .redis_manager
+                .set_list_element(id, &key, index, &value)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn push_redis_list_element(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    value: RedisValue,
+    end: ListEnd,
+) -> Result<RedisListElement, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[
+            match end {
+                ListEnd::Head => b"LPUSH",
+                ListEnd::Tail => b"RPUSH",
+            },
+            key.as_bytes(),
+            &value.bytes,
+        ],
+        async {
+            state
+                .redis_manager
+                .push_list_element(id, &key, &value, end)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn remove_redis_list_element(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    value: RedisValue,
+    count: i64,
+) -> Result<u64, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[
+            b"LREM",
+            key.as_bytes(),
+            count.to_string().as_bytes(),
+            &value.bytes,
+        ],
+        async {
+            state
+                .redis_manager
+                .remove_list_element(id, &key, &value, count)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn add_redis_set_member(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    member: RedisValue,
+) -> Result<RedisSetMember, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[b"SADD", key.as_bytes(), &member.bytes],
+        async {
+            state
+                .redis_manager
+                .add_set_member(id, &key, &member)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn remove_redis_set_member(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    member: RedisValue,
+) -> Result<RedisSetMember, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[b"SREM", key.as_bytes(), &member.bytes],
+        async {
+            state
+                .redis_manager
+                .remove_set_member(id, &key, &member)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn add_redis_zset_member(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    member: RedisValue,
+    score: f64,
+) -> Result<RedisZsetMember, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[
+            b"ZADD",
+            key.as_bytes(),
+            score.to_string().as_bytes(),
+            &member.bytes,
+        ],
+        async {
+            state
+                .redis_manager
+                .add_zset_member(id, &key, &member, score)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn increment_redis_zset_member(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    member: RedisValue,
+    delta: f64,
+) -> Result<RedisZsetMember, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[
+            b"ZINCRBY",
+            key.as_bytes(),
+            delta.to_string().as_bytes(),
+            &member.bytes,
+        ],
+        async {
+            state
+                .redis_manager
+                .increment_zset_member(id, &key, &member, delta)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn remove_redis_zset_member(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    member: RedisValue,
+) -> Result<RedisZsetMember, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(
+        &state,
+        id,
+        &[b"ZREM", key.as_bytes(), &member.bytes],
+        async {
+            state
+                .redis_manager
+                .remove_zset_member(id, &key, &member)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+async fn create_redis_key(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    payload: RedisKeyPayload,
+    ttl: Option<i64>,
+    overwrite: bool,
+) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    let snapshot = state
+        .redis_manager
+        .snapshot(id, &key)
+        .await
+        .map_err(|e| e.to_string())?;
+    let digests = vec![snapshot.digest()];
+    let started = Instant::now();
+
+    let create = state
+        .redis_manager
+        .create_key(id, &key, &payload, ttl, overwrite);
+    let result = state
+        .redis_manager
+        .with_snapshot(id, snapshot, create)
+        .await
+        .map_err(|e| e.to_string());
+    record_history(
+        &state,
+        id,
+        console::format_command(&[b"CREATE", key.as_bytes()]),
+        true,
+        started,
+        result.as_ref().err().cloned(),
+    );
+    result?;
+
+    record_audit(&state, id, b"CREATE", &[key.as_bytes()], digests);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_redis_key(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    value: RedisValue,
+) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(&state, id, &[b"SET", key.as_bytes(), &value.bytes], async {
+        state
+            .redis_manager
+            .set_key(id, &key, &value)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// The protobuf decoder for `key` from the schemas attached to connection `id`.
+fn schema_decoder(state: &AppState, id: i64, key: &str) -> Result<Option<SchemaDecoder>, String> {
+    let schemas: Vec<ProtoSchema> = state
+        .sqlite_manager
+        .list::<ProtoSchema>()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|schema| schema.connection_id == id)
+        .collect();
+
+    SchemaDecoder::for_key(&schemas, key)
+}
+
+#[tauri::command]
+fn create_proto_schema(
+    app_handler: tauri::AppHandle,
+    connection_id: i64,
+    name: String,
+    paths: Vec<String>,
+    mappings: Vec<ProtoMapping>,
+) -> Result<i64, String> {
+    let state = app_handler.state::<AppState>();
+
+    let descriptor_set = decoder::load_descriptor_set(&paths)?;
+    decoder::validate_schema(&descriptor_set, &mappings)?;
+
+    let schema = ProtoSchema {
+        id: 0,
+        connection_id,
+        name,
+        descriptor_set,
+        mappings,
+    };
+
+    state
+        .sqlite_manager
+        .insert(&schema)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_proto_schema(app_handler: tauri::AppHandle, entity: ProtoSchema) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    decoder::validate_schema(&entity.descriptor_set, &entity.mappings)?;
+
+    state
+        .sqlite_manager
+        .update(&entity)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_proto_schema(app_handler: tauri::AppHandle, id: i64) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    state
+        .sqlite_manager
+        .delete::<ProtoSchema>(id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_proto_schemas(
+    app_handler: tauri::AppHandle,
+    connection_id: i64,
+) -> Result<Vec<ProtoSchema>, String> {
+    let state = app_handler.state::<AppState>();
+
+    state
+        .sqlite_manager
+        .list::<ProtoSchema>()
+        .map(|schemas| {
+            schemas
+                .into_iter()
+                .filter(|schema| schema.connection_id == connection_id)
+                .collect()
+        })
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn decode_redis_value(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    formats: Option<Vec<ValueFormat>>,
+) -> Result<Option<DecodedValue>, String> {
+    let state = app_handler.state::<AppState>();
+
+    let info = state
+        .redis_manager
+        .get_key_info(id, &key)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match info {
+        Some(info) if info.data_type != "string" => {
+            Err(format!("Cannot decode a {} value", info.data_type))
+        }
+        Some(info) => {
+            let schema = schema_decoder(&state, id, &key)?;
+            let overrides: Vec<&dyn Decoder> = schema.iter().map(|d| d as &dyn Decoder).collect();
+
+            state
+                .decoders
+                .decode(&info.value.bytes, formats.as_deref(), &overrides)
+                .map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+async fn set_redis_decoded_value(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    formats: Vec<ValueFormat>,
+    view: serde_json::Value,
+) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    let schema = schema_decoder(&state, id, &key)?;
+    let overrides: Vec<&dyn Decoder> = schema.iter().map(|d| d as &dyn Decoder).collect();
+
+    let value = RedisValue::from_bytes(state.decoders.encode(&formats, view, &overrides)?);
+
+    run_edit(&state, id, &[b"SET", key.as_bytes(), &value.bytes], async {
+        state
+            .redis_manager
+            .set_key(id, &key, &value)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_redis_key(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    run_edit(&state, id, &[b"DEL", key.as_bytes()], async {
+        state
+            .redis_manager
+            .delete_key(id, &key)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn set_redis_ttl(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    ttl: i64,
+) -> Result<(), String> {
+    let state = app_handler.state::<AppState>();
+
+    let seconds = ttl.to_string();
+    let args: Vec<&[u8]> = if ttl < 0 {
+        vec![b"PERSIST", key.as_bytes()]
+    } else {
+        vec![b"EXPIRE", key.as_bytes(), seconds.as_bytes()]
+    };
+
+    run_edit(&state, id, &args, async {
+        state
+            .redis_manager
+            .set_ttl(id, &key, ttl)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn set_redis_ttl_by_pattern(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    pattern: String,
+    change: TtlChange,
+    condition: Option<TtlCondition>,
+    batch_size: usize,
+    operation_id: Option<String>,
+) -> Result<RedisTtlReport, String> {
+    let state = app_handler.state::<AppState>();
+    let operation = state.operations.start(operation_id);
+    let args = redis::ttl_pattern_args(&pattern, change, condition);
+    let args: Vec<&[u8]> = args.iter().map(Vec::as_slice).collect();
+    let started = Instant::now();
+
+    let result = state
+        .redis_manager
+        .set_ttl_by_pattern(
+            id,
+            &pattern,
+            change,
+            condition,
+            batch_size,
+            operation.token(),
+        )
+        .await
+        .map_err(|e| e.to_string());
+    record_history(
+        &state,
+        id,
+        console::format_command(&args),
+        true,
+        started,
+        result.as_ref().err().cloned(),
+    );
+    let report = result?;
+
+    // A cancelled run still reports and audits the batches it applied.
+    let keys: Vec<&[u8]> = report.changed_keys.iter().map(String::as_bytes).collect();
+    if !keys.is_empty() {
+        record_audit(&state, id, args[0], &keys, Vec::new());
+    }
+
+    Ok(report)
+}
+
+/// Opens a saved connection that is not connected yet, such as the target of
+/// a move.
+async fn ensure_connected(state: &AppState, id: i64) -> Result<(), String> {
+    if state.redis_manager.get_connection(id).is_ok() {
+        return Ok(());
+    }
+
+    let connection = state
+        .sqlite_manager
+        .get_by_id::<Connection>(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| String::from("Connection not found"))?;
+
+    state
+        .redis_manager
+        .set_read_only(id, connection.is_read_only());
+    state
+        .redis_manager
+        .connect(
+            id,
+            &connection.uri_connection,
+            None,
+            connection.tls.as_ref(),
+            connection.ssh.as_ref(),
+            connection.sentinel.as_ref(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The digest `destination` had before a RENAME or COPY with REPLACE, for the
+/// audit entry of the key it overwrites. None for any other `conflict`.
+async fn replaced_digest(
+    state: &AppState,
+    id: i64,
+    destination: &str,
+    db: Option<i64>,
+    conflict: ConflictPolicy,
+) -> Result<Option<Option<String>>, String> {
+    if !matches!(conflict, ConflictPolicy::Replace) {
+        return Ok(None);
+    }
+
+    let digest = match db {
+        Some(db) => {
+            state
+                .redis_manager
+                .value_digest_in(id, destination.as_bytes(), db)
+                .await
+        }
+        None => {
+            state
+                .redis_manager
+                .value_digest(id, destination.as_bytes())
+                .await
+        }
+    };
+    digest.map(Some).map_err(|e| e.to_string())
+}
+
+/// Renames `key`, returning false when it was skipped because `new_key`
+/// exists. Existing keys are not replaced unless `conflict` says so.
+#[tauri::command]
+async fn rename_redis_key(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    new_key: String,
+    conflict: Option<ConflictPolicy>,
+) -> Result<bool, String> {
+    let state = app_handler.state::<AppState>();
+    let conflict = conflict.unwrap_or(ConflictPolicy::Fail);
+
+    let command: &[u8] = match conflict {
+        ConflictPolicy::Replace => b"RENAME",
+        _ => b"RENAMENX",
+    };
+    let replaced = replaced_digest(&state, id, &new_key, None, conflict).await?;
+
+    let renamed = run_edit(
+        &state,
+        id,
+        &[command, key.as_bytes(), new_key.as_bytes()],
+        async {
+            state
+                .redis_manager
+                .rename_key(id, &key, &new_key, conflict)
+                .await
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await?;
+
+    if let Some(digest) = replaced {
+        record_audit(&state, id, command, &[new_key.as_bytes()], vec![digest]);
+    }
+    Ok(renamed)
+}
+
+#[tauri::command]
+async fn copy_redis_key(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    destination: String,
+    db: Option<i64>,
+    conflict: Option<ConflictPolicy>,
+) -> Result<bool, String> {
+    let state = app_handler.state::<AppState>();
+    let conflict = conflict.unwrap_or(ConflictPolicy::Fail);
+
+    let db_index = db.map(|db| db.to_string());
+    let mut args: Vec<&[u8]> = vec![b"COPY", key.as_bytes(), destination.as_bytes()];
+    if let Some(db_index) = &db_index {
+        args.extend([b"DB".as_slice(), db_index.as_bytes()]);
+    }
+    if let ConflictPolicy::Replace = conflict {
+        args.push(b"REPLACE");
+    }
+    let replaced = replaced_digest(&state, id, &destination, db, conflict).await?;
+
+    let copied = run_edit(&state, id, &args, async {
+        state
+            .redis_manager
+            .copy_key(id, &key, &destination, db, conflict)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await?;
+
+    if let Some(digest) = replaced {
+        record_audit(&state, id, b"COPY", &[destination.as_bytes()], vec![digest]);
+    }
+    Ok(copied)
+}
+
+/// Moves `key` to saved connection `target_id`, connecting to it first when
+/// needed. The key keeps its name unless `target_key` is given.
+#[tauri::command]
+async fn move_redis_key(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    key: String,
+    target_id: i64,
+    target_key: Option<String>,
+    conflict: Option<ConflictPolicy>,
+) -> Result<bool, String> {
+    let state = app_handler.state::<AppState>();
+    let conflict = conflict.unwrap_or(ConflictPolicy::Fail);
+    let target_key = target_key.unwrap_or_else(|| key.clone());
+
+    ensure_connected(&state, target_id).await?;
+
+    let digests = previous_digests(&state, id, &[key.as_bytes()]).await?;
+    let target_digests = previous_digests(&state, target_id, &[target_key.as_bytes()]).await?;
+    let started = Instant::now();
+
+    let result = state
+        .redis_manager
+        .move_key(id, &key, target_id, &target_key, conflict)
+        .await
+        .map_err(|e| e.to_string());
+    let target = target_id.to_string();
+    record_history(
+        &state,
+        id,
+        console::format_command(&[
+            b"MOVE",
+            key.as_bytes(),
+            target.as_bytes(),
+            target_key.as_bytes(),
+        ]),
+        true,
+        started,
+        result.as_ref().err().cloned(),
+    );
+    let moved = result?;
+
+    if moved {
+        record_audit(&state, id, b"MOVE", &[key.as_bytes()], digests);
+        record_audit(
+            &state,
+            target_id,
+            b"RESTORE",
+            &[target_key.as_bytes()],
+            target_digests,
+        );
+    }
+
+    Ok(moved)
+}
+
+/// Reverts the latest edit on connection `id`, returning the restored key.
+#[tauri::command]
+async fn undo_redis_edit(app_handler: tauri::AppHandle, id: i64) -> Result<Option<String>, String> {
+    let state = app_handler.state::<AppState>();
+
+    let Some(key) = state.redis_manager.undo_key(id) else {
+        return Ok(None);
+    };
+    let digests = previous_digests(&state, id, &[key.as_bytes()]).await?;
+    let started = Instant::now();
+
+    let result = state
+        .redis_manager
+        .undo_last(id)
+        .await
+        .map_err(|e| e.to_string());
+    record_history(
+        &state,
+        id,
+        console::format_command(&[b"UNDO", key.as_bytes()]),
+        true,
+        started,
+        result.as_ref().err().cloned(),
+    );
+    let restored = result?;
+
+    if let Some(key) = &restored {
+        record_audit(&state, id, b"UNDO", &[key.as_bytes()], digests);
+    }
+    Ok(restored)
+}
+
+#[tauri::command]
+async fn execute_redis_command(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    command: String,
+    confirmation: Option<String>,
+) -> Result<RedisReply, String> {
+    let state = app_handler.state::<AppState>();
+
+    run_console_command(&state, id, command, confirmation).await
+}
+
+/// The guarded-command entry that `args` falls under on connection `id`.
+fn guarded_by(state: &AppState, id: i64, args: &[Vec<u8>]) -> Result<Option<String>, String> {
+    let guarded = state
+        .sqlite_manager
+        .get_by_id::<Connection>(id)
+        .map_err(|e| e.to_string())?
+        .map(|connection| connection.guarded_commands)
+        .unwrap_or_else(guard::default_guarded_commands);
+
+    Ok(guard::guarded_by(&guarded, args).map(String::from))
+}
+
+async fn run_console_command(
+    state: &AppState,
+    id: i64,
+    command: String,
+    confirmation: Option<String>,
+) -> Result<RedisReply, String> {
+    let args = console::tokenize(&command)?;
+    console::check_supported(&args)?;
+
+    if let Some(rule) = guarded_by(state, id, &args)? {
+        let confirmed =
+            confirmation.is_some_and(|token| state.confirmations.consume(&token, id, &args));
+        if !confirmed {
+            return Err(format!("{} requires confirmation", rule));
+        }
+    }
+
+    let writes = state
+        .redis_manager
+        .command_kind(id, &args)
+        .await
+        .map_err(|e| e.to_string())?
+        .writes;
+    let keys = match writes {
+        true => state
+            .redis_manager
+            .command_keys(id, &args)
+            .await
+            .map_err(|e| e.to_string())?,
+        false => Vec::new(),
+    };
+    let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+    let digests = previous_digests(state, id, &keys).await?;
+
+    let started = Instant::now();
+    let result = state
+        .redis_manager
+        .execute_command(id, &args)
+        .await
+        .map_err(|e| e.to_string());
+
+    let error = match &result {
+        Ok(RedisReply::Error(message)) => Some(message.clone()),
+        Ok(_) => None,
+        Err(e) => Some(e.clone()),
+    };
+    if writes && error.is_none() {
+        record_audit(state, id, &args[0], &keys, digests);
+    }
+    record_history(state, id, command.trim().to_string(), false, started, error);
+
+    result
+}
+
+/// Issues the token that lets a guarded `command` run once on connection `id`,
+/// to be requested after the user confirms it.
+#[tauri::command]
+fn confirm_redis_command(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    command: String,
+) -> Result<String, String> {
+    let state = app_handler.state::<AppState>();
+
+    let args = console::tokenize(&command)?;
+    if guarded_by(&state, id, &args)?.is_none() {
+        return Err(String::from("Command does not require confirmation"));
+    }
+
+    Ok(state.confirmations.issue(id, &args))
+}
+
+/// History of connection `connection_id`, newest first, keeping entries whose
+/// command contains `query` regardless of case.
+#[tauri::command]
+fn search_command_history(
+    app_handler: tauri::AppHandle,
+    connection_id: i64,
+    query: String,
+    limit: u32,
+) -> Result<Vec<HistoryEntry>, String> {
+    let state = app_handler.state::<AppState>();
+
+    state
+        .sqlite_manager
+        .query::<HistoryEntry>(
+            "json_extract(data, '$.connection_id') = ?1
+                AND instr(lower(json_extract(data, '$.command')), lower(?2)) > 0
+                ORDER BY id DESC LIMIT ?3",
+            params![connection_id, query, limit],
+        )
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn rerun_command_history(
+    app_handler: tauri::AppHandle,
+    id: i64,
+    confirmation: Option<String>,
+) -> Result<RedisReply, String> {
+    let state = app_handler.state::<AppState>();
+
+    let entry = state
+        .sqlite_manager
+        .get_by_id::<HistoryEntry>(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| String::from("History entry not found"))?;
+    if entry.composite {
+        return Err(String::from("History entry cannot be run again"));
+    }
+
+    run_console_command(&state, entry.connection_id, entry.command, confirmation).await
+}
+
+/// Deletes the history of connection `connection_id` beyond the newest
+/// `max_entries` and older than `max_age_secs`. Returns how many entries were
+/// removed.
+#[tauri::command]
+fn prune_command_history(
+    app_handler: tauri::AppHandle,
+    connection_id: i64,
+    max_entries: Option<u32>,
+    max_age_secs: Option<u64>,
+) -> Result<usize, String> {
+    let state = app_handler.state::<AppState>();
+
+    history::prune(
+        &state.sqlite_manager,
+        connection_id,
+        max_entries,
+        max_age_secs,
+        unix_millis(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn query_audit_log(
+    app_handler: tauri::AppHandle,
+    filter: AuditFilter,
+    limit: Option<u32>,
+) -> Result<Vec<AuditEntry>, String> {
+    let state = app_handler.state::<AppState>();
+
+    audit::query(&state.sqlite_manager, &filter, limit).map_err(|e| e.to_string())
+}
+
+/// Writes the entries matching `filter` to `path`, returning how many were
+/// exported.
+#[tauri::command]
+fn export_audit_log(
+    app_handler: tauri::AppHandle,
+    filter: AuditFilter,
+    format: ExportFormat,
+    path: String,
+) -> Result<usize, String> {
+    let state = app_handler.state::<AppState>();
+
+    let entries = audit::query(&state.sqlite_manager, &filter, None).map_err(|e| e.to_string())?;
+    let contents = audit::export(&entries, format)?;
+    std::fs::write(&path, contents).map_err(|e| format!("{}: {}", path, e))?;
+
+    Ok(entries.len())
+}
+
+#[tauri::command]
+fn cancel_operation(app_handler: tauri::AppHandle, operation_id: String) -> bool {
+    let state = app_handler.state::<AppState>();
+
+    state.operations.cancel(&operation_id)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let sqlite_manager = SqliteManager::new().unwrap();
@@ -177,7 +1705,10 @@ pub fn run() {
     tauri::Builder::default()
         .manage(AppState {
             sqlite_manager,
-            redis_manager: Mutex::new(redis_manager),
+            redis_manager,
+            operations: OperationRegistry::new(),
+            decoders: DecoderRegistry::default(),
+            confirmations: ConfirmationRegistry::new(),
         })
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
@@ -187,12 +1718,58 @@ pub fn run() {
             delete_connection,
             list_connection,
             connect_redis,
+            get_ssh_host_key,
             disconnect_redis,
+            select_redis_db,
+            get_redis_selected_db,
+            get_redis_keyspace,
             get_redis_keys,
+            scan_redis_keys,
+            stream_redis_keys,
+            preview_redis_bulk_delete,
+            delete_redis_keys_by_pattern,
             get_redis_key_info,
+            get_redis_value_page,
+            get_redis_stream_range,
+            add_redis_stream_entry,
+            delete_redis_stream_entries,
+            trim_redis_stream,
+            get_redis_stream_groups,
+            get_redis_stream_consumers,
+            get_redis_stream_pending,
+            set_redis_hash_field,
+            delete_redis_hash_field,
+            set_redis_list_element,
+            push_redis_list_element,
+            remove_redis_list_element,
+            add_redis_set_member,
+            remove_redis_set_member,
+            add_redis_zset_member,
+            increment_redis_zset_member,
+            remove_redis_zset_member,
+            create_redis_key,
             set_redis_key,
+            decode_redis_value,
+            create_proto_schema,
+            update_proto_schema,
+            delete_proto_schema,
+            list_proto_schemas,
+            set_redis_decoded_value,
             delete_redis_key,
-            set_redis_ttl
+            set_redis_ttl,
+            set_redis_ttl_by_pattern,
+            rename_redis_key,
+            copy_redis_key,
+            move_redis_key,
+            undo_redis_edit,
+            execute_redis_command,
+            confirm_redis_command,
+            search_command_history,
+            rerun_command_history,
+            prune_command_history,
+            query_audit_log,
+            export_audit_log,
+            cancel_operation
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
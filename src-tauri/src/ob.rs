@@ -1,13 +1,95 @@
 use serde::{Deserialize, Serialize};
 
+use crate::guard::default_guarded_commands;
 use crate::sqlite::Entity;
 
+/// A saved connection. `read_only` rejects every write made through it, and
+/// `guarded_commands` lists the commands that need a confirmation token.
+/// `tls` is set for servers that need TLS, which a `rediss://` URI also turns
+/// on with the default settings, and `ssh` for servers only reachable through
+/// an SSH server. With `sentinel` the host and port of the URI are replaced by
+/// the node the sentinels point to.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Connection {
     pub id: i64,
     pub uri_connection: String,
     pub name: String,
     pub color: String,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default = "default_guarded_commands")]
+    pub guarded_commands: Vec<String>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub ssh: Option<SshConfig>,
+    #[serde(default)]
+    pub sentinel: Option<SentinelConfig>,
+}
+
+impl Connection {
+    /// Whether writes are rejected, either by choice or because the
+    /// connection goes to a replica.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+            || self
+                .sentinel
+                .as_ref()
+                .is_some_and(|sentinel| sentinel.replica)
+    }
+}
+
+/// TLS settings of a connection. Without `ca_path` the server certificate is
+/// checked against the web PKI roots, and `insecure` skips verification. The
+/// certificate has to be valid for `server_name` when it is set, and otherwise
+/// for the host the client connects to, which is 127.0.0.1 for a connection
+/// tunnelled through SSH and the address the sentinels report for a Sentinel
+/// connection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub ca_path: Option<String>,
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+    #[serde(default)]
+    pub server_name: Option<String>,
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+/// SSH server a connection is tunnelled through. The host and port of the
+/// connection URI are resolved from that server. `private_key_path`, with its
+/// `passphrase` if it has one, is used to log in, or `password` without it.
+/// `host_key_fingerprint` is the SHA256 fingerprint of the server key the
+/// user confirmed. Without it only keys in ~/.ssh/known_hosts are accepted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshConfig {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    pub password: Option<String>,
+    pub private_key_path: Option<String>,
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub host_key_fingerprint: Option<String>,
+}
+
+/// Sentinels watching the master a connection is for. `addresses` are
+/// `host:port` pairs asked in order, and `username` and `password` log in to
+/// the sentinels rather than the master. `replica` connects to one of the
+/// master's replicas instead, for read-only browsing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SentinelConfig {
+    pub addresses: Vec<String>,
+    pub master_name: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub replica: bool,
 }
 
 impl Entity for Connection {
@@ -28,3 +110,111 @@ impl Entity for Connection {
         )
     }
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProtoMapping {
+    pub pattern: String,
+    pub message_type: String,
+}
+
+/// Protobuf descriptors attached to a connection. `descriptor_set` is a
+/// base64 encoded FileDescriptorSet and `mappings` pick the message type used
+/// for keys matching a Redis glob pattern.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProtoSchema {
+    pub id: i64,
+    pub connection_id: i64,
+    pub name: String,
+    pub descriptor_set: String,
+    pub mappings: Vec<ProtoMapping>,
+}
+
+impl Entity for ProtoSchema {
+    fn get_table_name() -> String {
+        String::from("proto_schemas")
+    }
+
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    fn get_create_table_sql() -> String {
+        String::from(
+            "CREATE TABLE IF NOT EXISTS proto_schemas (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                data TEXT NOT NULL
+            )",
+        )
+    }
+}
+
+/// A command run against a connection from the console or an edit in the key
+/// view. `command` is a redis-cli style line, so an entry can be run again,
+/// unless it is `composite`: a summary of an operation made of many commands,
+/// such as a delete by pattern or a move. `timestamp` is when it started, in
+/// milliseconds since the Unix epoch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub connection_id: i64,
+    pub command: String,
+    pub timestamp: i64,
+    pub duration_ms: u64,
+    pub success: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub composite: bool,
+}
+
+impl Entity for HistoryEntry {
+    fn get_table_name() -> String {
+        String::from("command_history")
+    }
+
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    fn get_create_table_sql() -> String {
+        String::from(
+            "CREATE TABLE IF NOT EXISTS command_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                data TEXT NOT NULL
+            )",
+        )
+    }
+}
+
+/// A mutation made through Setui. `previous_digest` is the SHA-256 of the
+/// key's DUMP payload before the change, absent when the key did not exist
+/// or the operation has no key. Entries outlive the connection they name.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub connection_id: i64,
+    pub connection_name: String,
+    pub user: String,
+    pub key: Option<String>,
+    pub operation: String,
+    pub previous_digest: Option<String>,
+    pub timestamp: i64,
+}
+
+impl Entity for AuditEntry {
+    fn get_table_name() -> String {
+        String::from("audit_log")
+    }
+
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    fn get_create_table_sql() -> String {
+        String::from(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                data TEXT NOT NULL
+            )",
+        )
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OperationRegistry {
+    operations: Mutex<HashMap<String, CancellationToken>>,
+}
+
+/// A running operation, removed from the registry when dropped.
+pub struct Operation<'a> {
+    registry: &'a OperationRegistry,
+    id: Option<String>,
+    token: CancellationToken,
+}
+
+impl Operation<'_> {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for Operation<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            self.registry.operations.lock().unwrap().remove(id);
+        }
+    }
+}
+
+impl OperationRegistry {
+    pub fn new() -> Self {
+        OperationRegistry {
+            operations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts an operation, registering it under `id` when one is given so it
+    /// can be cancelled later.
+    pub fn start(&self, id: Option<String>) -> Operation<'_> {
+        let token = CancellationToken::default();
+
+        if let Some(id) = &id {
+            self.operations
+                .lock()
+                .unwrap()
+                .insert(id.clone(), token.clone());
+        }
+
+        Operation {
+            registry: self,
+            id,
+            token,
+        }
+    }
+
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.operations.lock().unwrap().get(id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_running_operation() {
+        let registry = OperationRegistry::new();
+        let operation = registry.start(Some("scan".to_string()));
+
+        assert!(!operation.token().is_cancelled());
+
+        assert!(registry.cancel("scan"));
+        assert!(operation.token().is_cancelled());
+    }
+
+    #[test]
+    fn finished_operation_is_removed() {
+        let registry = OperationRegistry::new();
+
+        {
+            let _operation = registry.start(Some("scan".to_string()));
+            assert!(registry.operations.lock().unwrap().contains_key("scan"));
+        }
+
+        assert!(registry.operations.lock().unwrap().is_empty());
+        assert!(!registry.cancel("scan"));
+    }
+
+    #[test]
+    fn anonymous_operation() {
+        let registry = OperationRegistry::new();
+        let operation = registry.start(None);
+
+        assert!(!operation.token().is_cancelled());
+        assert!(registry.operations.lock().unwrap().is_empty());
+    }
+}
@@ -1,282 +1,4226 @@
-use redis::{Client, Commands, Connection as RedisConnection, RedisError, RedisResult};
+use redis::aio::{ConnectionLike, ConnectionManager, ConnectionManagerConfig};
+use redis::streams::{StreamInfoConsumersReply, StreamInfoGroupsReply, StreamPendingCountReply};
+use redis::{
+    AsyncCommands, Client, ClientTlsConfig, Cmd, ConnectionAddr, ConnectionInfo,
+    IntoConnectionInfo, Pipeline, RedisError, RedisFuture, RedisResult, TlsCertificates, Value,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 
-#[derive(Debug, Default)]
+use crate::console::{self, RedisReply};
+use crate::encoding::RedisValue;
+use crate::ob::{SentinelConfig, SshConfig, TlsConfig};
+use crate::operation::CancellationToken;
+use crate::sentinel::{Node, SentinelMonitor};
+use crate::ssh::SshTunnel;
+use crate::tls::{NamedTls, NamedTlsConnection};
+
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECTION_RETRIES: usize = 2;
+/// Longest wait between connection attempts, in milliseconds. The default
+/// backoff waits over a minute before the last retry, which a certificate
+/// that will never verify is not worth.
+const MAX_RETRY_DELAY_MS: u64 = 1000;
+/// Edits kept for undo on each connection, oldest dropped first.
+const UNDO_LIMIT: usize = 50;
+/// How many bytes of DUMP payloads the undo history of a connection may hold.
+const UNDO_BYTES: usize = 64 * 1024 * 1024;
+/// Keys whose DUMP is larger than this are edited without a snapshot, which
+/// also clears the undo history since the edits before can no longer be
+/// undone in order.
+const MAX_UNDO_KEY_BYTES: usize = 8 * 1024 * 1024;
+
+tokio::task_local! {
+    /// A snapshot taken before an edit, for `begin_edit` to use instead of
+    /// dumping the key again. See `RedisManager::with_snapshot`.
+    static TAKEN_SNAPSHOT: RefCell<Option<(i64, KeySnapshot)>>;
+}
+
+/// Keeps one multiplexed connection per connection id. The map is only
+/// write-locked while connecting or disconnecting, every other call clones the
+/// handle out, so commands never wait on each other. Connections reconnect
+/// on their own when the socket drops. `targets` keeps what each
+/// connection was opened with, including the selected database and the TLS
+/// certificates. Connections in `read_only` reject every command that writes,
+/// and `undo` holds the state of keys before recent edits. Connections over
+/// SSH or Sentinel keep what they go through in `tunnels` for as long as they
+/// are open. `reopening` is held while an open connection is replaced, by
+/// `select_db` or after a failover, so neither installs one made from info
+/// the other just changed.
+#[derive(Default)]
 pub struct RedisManager {
-    connections: HashMap<i64, Client>,
+    connections: Arc<RwLock<HashMap<i64, RedisConnection>>>,
+    targets: Arc<RwLock<HashMap<i64, Target>>>,
+    read_only: RwLock<HashSet<i64>>,
+    undo: Mutex<HashMap<i64, VecDeque<KeySnapshot>>>,
+    tunnels: Mutex<HashMap<i64, Tunnels>>,
+    reopening: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// The SSH tunnel a connection goes through, or the Sentinel monitor telling
+/// it where the node is and the task moving it there after a failover.
+#[derive(Default)]
+struct Tunnels {
+    ssh: Option<SshTunnel>,
+    sentinel: Option<SentinelMonitor>,
+    follower: Option<JoinHandle<()>>,
+}
+
+impl Drop for Tunnels {
+    fn drop(&mut self) {
+        if let Some(follower) = &self.follower {
+            follower.abort();
+        }
+    }
+}
+
+/// An open connection. TLS connections that check the certificate against a
+/// server name of their own are made by `NamedTlsConnection`, the others by
+/// the connection manager. Both reconnect when the socket drops.
+#[derive(Clone)]
+pub enum RedisConnection {
+    Managed(Box<ConnectionManager>),
+    NamedTls(NamedTlsConnection),
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConnection::Managed(conn) => conn.req_packed_command(cmd),
+            RedisConnection::NamedTls(conn) => conn.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConnection::Managed(conn) => conn.req_packed_commands(cmd, offset, count),
+            RedisConnection::NamedTls(conn) => conn.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Managed(conn) => conn.get_db(),
+            RedisConnection::NamedTls(conn) => conn.get_db(),
+        }
+    }
+}
+
+/// What a connection is opened with: the address, credentials and database
+/// in `info`, and `tls` when it checks a server name of its own.
+#[derive(Clone)]
+struct Target {
+    info: ConnectionInfo,
+    tls: Option<Arc<NamedTls>>,
+}
+
+/// A key as it was before an edit. `dump` is None when the key did not
+/// exist, and `pttl` is -1 for a key without expiry.
+#[derive(Debug)]
+pub struct KeySnapshot {
+    key: String,
+    dump: Option<Vec<u8>>,
+    pttl: i64,
+}
+
+impl KeySnapshot {
+    /// SHA-256 of the DUMP payload, or None when the key did not exist.
+    pub fn digest(&self) -> Option<String> {
+        self.dump.as_deref().map(dump_digest)
+    }
+
+    fn size(&self) -> usize {
+        self.dump.as_ref().map_or(0, Vec::len)
+    }
+}
+
+fn dump_digest(dump: &[u8]) -> String {
+    hex::encode(Sha256::digest(dump))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisKeyValue {
+    pub key: String,
+    pub value: RedisValue,
+    pub ttl: i64,
+    pub data_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisKeyPage {
+    pub cursor: u64,
+    pub keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "items", rename_all = "camelCase")]
+pub enum RedisValueSlice {
+    List(Vec<RedisValue>),
+    Set(Vec<RedisValue>),
+    Hash(Vec<(RedisValue, RedisValue)>),
+    Zset(Vec<(RedisValue, f64)>),
+}
+
+/// One slice of a collection value. `cursor` is the LRANGE offset for lists
+/// and the xSCAN cursor for the other types; 0 means there is nothing left.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisValuePage {
+    pub key: String,
+    pub total: u64,
+    pub cursor: u64,
+    pub slice: RedisValueSlice,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisStreamEntry {
+    pub id: String,
+    pub fields: Vec<(RedisValue, RedisValue)>,
+}
+
+/// A page of stream entries. `next` is the exclusive bound to pass as `start`
+/// to fetch the following page, `None` once the end of the stream is reached.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisStreamPage {
+    pub key: String,
+    pub total: u64,
+    pub entries: Vec<RedisStreamEntry>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "strategy", content = "threshold", rename_all = "camelCase")]
+pub enum StreamTrim {
+    MaxLen(u64),
+    MinId(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisStreamGroup {
+    pub name: String,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered_id: String,
+    pub lag: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisStreamConsumer {
+    pub name: String,
+    pub pending: usize,
+    pub idle: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisStreamPendingEntry {
+    pub id: String,
+    pub consumer: String,
+    pub idle: usize,
+    pub delivered: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum RedisKeyPayload {
+    String(RedisValue),
+    List(Vec<RedisValue>),
+    Set(Vec<RedisValue>),
+    Hash(Vec<(RedisValue, RedisValue)>),
+    Zset(Vec<(RedisValue, f64)>),
+    Stream(Vec<RedisStreamEntry>),
+}
+
+impl RedisKeyPayload {
+    fn is_empty(&self) -> bool {
+        match self {
+            RedisKeyPayload::String(_) => false,
+            RedisKeyPayload::List(values) | RedisKeyPayload::Set(values) => values.is_empty(),
+            RedisKeyPayload::Hash(fields) => fields.is_empty(),
+            RedisKeyPayload::Zset(members) => members.is_empty(),
+            RedisKeyPayload::Stream(entries) => entries.is_empty(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisHashField {
+    pub field: RedisValue,
+    pub value: Option<RedisValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisListElement {
+    pub index: i64,
+    pub value: Option<RedisValue>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ListEnd {
+    Head,
+    Tail,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisSetMember {
+    pub member: RedisValue,
+    pub exists: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisZsetMember {
+    pub member: RedisValue,
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "camelCase")]
+pub enum ScanEvent {
+    Batch {
+        keys: Vec<String>,
+        scanned: u64,
+        total: u64,
+    },
+    Finished {
+        scanned: u64,
+        total: u64,
+    },
+    Cancelled {
+        scanned: u64,
+        total: u64,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RedisKeyPreview {
+    pub count: u64,
+    pub sample: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "camelCase")]
+pub enum DeleteEvent {
+    Progress {
+        deleted: u64,
+        scanned: u64,
+        total: u64,
+    },
+    Finished {
+        deleted: u64,
+    },
+    Cancelled {
+        deleted: u64,
+    },
+    Error {
+        message: String,
+        deleted: u64,
+    },
+}
+
+/// The expiry applied by `set_ttl_by_pattern`: seconds or milliseconds from
+/// now, a Unix time in seconds, or no expiry at all.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "command", content = "value", rename_all = "camelCase")]
+pub enum TtlChange {
+    Expire(i64),
+    PExpire(i64),
+    ExpireAt(i64),
+    Persist,
+}
+
+/// Limits an expiry to keys without a TTL (`Nx`), with one (`Xx`), or whose
+/// TTL it extends (`Gt`) or shortens (`Lt`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TtlCondition {
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+/// A line of the keyspace section of INFO. `avg_ttl` is in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedisKeyspace {
+    pub db: i64,
+    pub keys: u64,
+    pub expires: u64,
+    pub avg_ttl: u64,
+}
+
+/// Parses lines such as `db0:keys=1,expires=0,avg_ttl=0`, skipping anything
+/// else and fields it does not know.
+fn parse_keyspace(info: &str) -> Vec<RedisKeyspace> {
+    info.lines()
+        .filter_map(|line| {
+            let (db, fields) = line.trim().split_once(':')?;
+            let mut keyspace = RedisKeyspace {
+                db: db.strip_prefix("db")?.parse().ok()?,
+                keys: 0,
+                expires: 0,
+                avg_ttl: 0,
+            };
+
+            for field in fields.split(',') {
+                let (name, value) = field.split_once('=')?;
+                let value = value.parse().ok()?;
+                match name {
+                    "keys" => keyspace.keys = value,
+                    "expires" => keyspace.expires = value,
+                    "avg_ttl" => keyspace.avg_ttl = value,
+                    _ => {}
+                }
+            }
+
+            Some(keyspace)
+        })
+        .collect()
+}
+
+/// `changed_keys` lists the keys counted in `changed`, for the audit log. It
+/// is not sent to the UI.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RedisTtlReport {
+    pub changed: u64,
+    pub skipped: u64,
+    pub missing: u64,
+    pub cancelled: bool,
+    #[serde(skip)]
+    pub changed_keys: Vec<String>,
+}
+
+/// Rejects a change that expires keys on the spot, which makes Redis delete
+/// them. Deleting by pattern has its own preview and confirmation. `now` is
+/// the Unix time in seconds.
+fn check_ttl_change(change: TtlChange, now: i64) -> RedisResult<()> {
+    let deletes = match change {
+        TtlChange::Expire(ttl) | TtlChange::PExpire(ttl) => ttl <= 0,
+        TtlChange::ExpireAt(timestamp) => timestamp <= now,
+        TtlChange::Persist => false,
+    };
+
+    if deletes {
+        return Err(RedisError::from((
+            redis::ErrorKind::ClientError,
+            "Expiry must be in the future",
+        )));
+    }
+
+    Ok(())
+}
+
+fn ttl_command(key: &str, change: TtlChange, condition: Option<TtlCondition>) -> redis::Cmd {
+    let (name, value) = match change {
+        TtlChange::Expire(seconds) => ("EXPIRE", seconds),
+        TtlChange::PExpire(millis) => ("PEXPIRE", millis),
+        TtlChange::ExpireAt(timestamp) => ("EXPIREAT", timestamp),
+        TtlChange::Persist => return redis::cmd("PERSIST").arg(key).clone(),
+    };
+
+    let mut cmd = redis::cmd(name);
+    cmd.arg(key).arg(value);
+
+    if let Some(condition) = condition {
+        cmd.arg(match condition {
+            TtlCondition::Nx => "NX",
+            TtlCondition::Xx => "XX",
+            TtlCondition::Gt => "GT",
+            TtlCondition::Lt => "LT",
+        });
+    }
+
+    cmd
+}
+
+/// The command `set_ttl_by_pattern` sends for each key, with `pattern` in
+/// place of the key, to describe the whole change.
+pub fn ttl_pattern_args(
+    pattern: &str,
+    change: TtlChange,
+    condition: Option<TtlCondition>,
+) -> Vec<Vec<u8>> {
+    ttl_command(pattern, change, condition)
+        .args_iter()
+        .filter_map(|arg| match arg {
+            redis::Arg::Simple(arg) => Some(arg.to_vec()),
+            redis::Arg::Cursor => None,
+        })
+        .collect()
+}
+
+/// What to do when the target of a rename, copy or move already exists.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictPolicy {
+    Skip,
+    Replace,
+    Fail,
+}
+
+fn invalid_config_error(message: &'static str) -> RedisError {
+    RedisError::from((redis::ErrorKind::InvalidClientConfig, message))
+}
+
+/// Reads the PEM files named in `tls`.
+fn tls_certificates(tls: &TlsConfig) -> RedisResult<TlsCertificates> {
+    let read = |path: &String| {
+        std::fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+    };
+
+    let client_tls = match (&tls.client_cert_path, &tls.client_key_path) {
+        (Some(cert), Some(key)) => Some(ClientTlsConfig {
+            client_cert: read(cert)?,
+            client_key: read(key)?,
+        }),
+        (None, None) => None,
+        _ => {
+            return Err(invalid_config_error(
+                "A client certificate needs both a certificate and a key",
+            ))
+        }
+    };
+
+    Ok(TlsCertificates {
+        client_tls,
+        root_cert: tls.ca_path.as_ref().map(read).transpose()?,
+    })
+}
+
+/// Parses `uri` into what a connection is opened with. TLS is on for a
+/// `rediss://` URI, where an `#insecure` fragment skips certificate
+/// verification as it does in redis-cli, and whenever `tls` is given. The
+/// certificates in `tls` are loaded here and kept in the result, so
+/// reconnecting does not read them again. With a `server_name`, TLS is left
+/// to a `NamedTls` and the address kept as plain TCP.
+fn connection_info(uri: &str, tls: Option<&TlsConfig>) -> RedisResult<Target> {
+    let mut info = uri.into_connection_info()?;
+    let Some(tls) = tls else {
+        return Ok(Target { info, tls: None });
+    };
+
+    info.addr = match info.addr {
+        ConnectionAddr::Tcp(host, port) => ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure: tls.insecure,
+            tls_params: None,
+        },
+        ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure,
+            ..
+        } => ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure: insecure || tls.insecure,
+            tls_params: None,
+        },
+        _ => return Err(invalid_config_error("TLS needs a TCP address")),
+    };
+
+    if let (
+        Some(server_name),
+        ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure: false,
+            ..
+        },
+    ) = (&tls.server_name, &info.addr)
+    {
+        let named = NamedTls::new(tls, server_name)?;
+        info.addr = ConnectionAddr::Tcp(host.clone(), *port);
+        return Ok(Target {
+            info,
+            tls: Some(Arc::new(named)),
+        });
+    }
+
+    let client = Client::build_with_tls(info, tls_certificates(tls)?)?;
+    Ok(Target {
+        info: client.get_connection_info().clone(),
+        tls: None,
+    })
+}
+
+fn connection_not_found_error() -> RedisError {
+    RedisError::from((redis::ErrorKind::ResponseError, "Connection not found"))
+}
+
+fn tunnel_closed_error() -> RedisError {
+    RedisError::from((redis::ErrorKind::IoError, "SSH tunnel closed"))
+}
+
+fn key_exists_error() -> RedisError {
+    RedisError::from((redis::ErrorKind::ResponseError, "Key already exists"))
+}
+
+fn no_such_key_error() -> RedisError {
+    RedisError::from((redis::ErrorKind::ResponseError, "No such key"))
+}
+
+/// Whether an operation that may have hit an existing target went ahead,
+/// failing instead of skipping when `conflict` says so.
+fn resolve_conflict(done: bool, conflict: ConflictPolicy) -> RedisResult<bool> {
+    match (done, conflict) {
+        (true, _) => Ok(true),
+        (false, ConflictPolicy::Fail) => Err(key_exists_error()),
+        (false, _) => Ok(false),
+    }
+}
+
+fn unsupported_type_error(key_type: &str) -> RedisError {
+    RedisError::from((
+        redis::ErrorKind::TypeError,
+        "Unsupported type",
+        key_type.to_string(),
+    ))
+}
+
+fn cancelled_error() -> RedisError {
+    RedisError::from((redis::ErrorKind::ClientError, "Operation cancelled"))
+}
+
+fn read_only_error() -> RedisError {
+    RedisError::from((redis::ErrorKind::ReadOnly, "Connection is read-only"))
+}
+
+/// Deletes KEYS[1] only while its DUMP payload is still ARGV[1].
+const DELETE_UNCHANGED_SCRIPT: &str = r"
+if redis.call('DUMP', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+async fn take_snapshot(conn: &mut RedisConnection, key: &str) -> RedisResult<KeySnapshot> {
+    let (dump, pttl): (Option<Vec<u8>>, i64) = redis::pipe()
+        .atomic()
+        .cmd("DUMP")
+        .arg(key)
+        .pttl(key)
+        .query_async(conn)
+        .await?;
+
+    Ok(KeySnapshot {
+        key: key.to_string(),
+        dump,
+        pttl,
+    })
+}
+
+/// Commands the server flags neither as writing nor as admin that still
+/// change the server for every client.
+const UNFLAGGED_ADMIN_COMMANDS: [(&str, &str); 3] = [
+    ("SCRIPT", "FLUSH"),
+    ("SCRIPT", "KILL"),
+    ("FUNCTION", "KILL"),
+];
+
+/// What a command does according to COMMAND INFO.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CommandKind {
+    /// Flagged `write` or `may_replicate`.
+    pub writes: bool,
+    /// Flagged `admin` or in the `@dangerous` category.
+    pub admin: bool,
+}
+
+/// The COMMAND INFO entry of `name`, which can be a `container|subcommand`
+/// pair, or None for an unknown command. A reply that cannot be read gives
+/// an empty entry.
+async fn command_details(
+    conn: &mut RedisConnection,
+    name: &[u8],
+) -> RedisResult<Option<Vec<redis::Value>>> {
+    let info: redis::Value = redis::cmd("COMMAND")
+        .arg("INFO")
+        .arg(name)
+        .query_async(conn)
+        .await?;
+
+    let details = match info {
+        redis::Value::Array(mut commands) if commands.len() == 1 => commands.remove(0),
+        _ => return Ok(Some(Vec::new())),
+    };
+
+    match details {
+        redis::Value::Nil => Ok(None),
+        redis::Value::Array(details) => Ok(Some(details)),
+        _ => Ok(Some(Vec::new())),
+    }
+}
+
+/// The names in field `index` of a COMMAND INFO entry.
+fn detail_names(details: &[redis::Value], index: usize) -> Option<Vec<&str>> {
+    match details.get(index)? {
+        redis::Value::Array(names) | redis::Value::Set(names) => names
+            .iter()
+            .map(|name| match name {
+                redis::Value::SimpleString(name) => Some(name.as_str()),
+                redis::Value::BulkString(name) => std::str::from_utf8(name).ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Asks the server what the command in `args` does, so module commands are
+/// covered too. Containers such as CONFIG or XGROUP have no flags of their
+/// own, so their subcommand is looked up instead. Scripts and functions
+/// count as writes unless run through their `_RO` variants. Replies that
+/// cannot be read count as both writing and admin.
+async fn command_kind(conn: &mut RedisConnection, args: &[Vec<u8>]) -> RedisResult<CommandKind> {
+    let Some(name) = args.first() else {
+        return Ok(CommandKind::default());
+    };
+    let Some(mut details) = command_details(conn, name).await? else {
+        return Ok(CommandKind::default());
+    };
+
+    let is_container =
+        matches!(details.get(9), Some(redis::Value::Array(subcommands)) if !subcommands.is_empty());
+    if let (true, Some(subcommand)) = (is_container, args.get(1)) {
+        let mut full_name = name.to_ascii_lowercase();
+        full_name.push(b'|');
+        full_name.extend(subcommand.to_ascii_lowercase());
+
+        match command_details(conn, &full_name).await? {
+            Some(subcommand_details) => details = subcommand_details,
+            None => return Ok(CommandKind::default()),
+        }
+    }
+
+    let Some(flags) = detail_names(&details, 2) else {
+        return Ok(CommandKind {
+            writes: true,
+            admin: true,
+        });
+    };
+    let categories = detail_names(&details, 6).unwrap_or_default();
+    let unflagged_admin = UNFLAGGED_ADMIN_COMMANDS
+        .iter()
+        .any(|(command, subcommand)| {
+            name.eq_ignore_ascii_case(command.as_bytes())
+                && args
+                    .get(1)
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case(subcommand.as_bytes()))
+        });
+
+    Ok(CommandKind {
+        writes: flags
+            .iter()
+            .any(|flag| matches!(*flag, "write" | "may_replicate")),
+        admin: flags.contains(&"admin") || categories.contains(&"@dangerous") || unflagged_admin,
+    })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RedisKeyValue {
-    pub key: String,
-    pub value: String,
-    pub ttl: i64,
-    pub data_type: String,
-}
+impl RedisManager {
+    pub fn new() -> Self {
+        RedisManager {
+            connections: Arc::new(RwLock::new(HashMap::new())),
+            targets: Arc::new(RwLock::new(HashMap::new())),
+            read_only: RwLock::new(HashSet::new()),
+            undo: Mutex::new(HashMap::new()),
+            tunnels: Mutex::new(HashMap::new()),
+            reopening: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    async fn open(target: Target) -> RedisResult<RedisConnection> {
+        if let Some(tls) = target.tls {
+            let conn = NamedTlsConnection::open(target.info, tls).await?;
+            return Ok(RedisConnection::NamedTls(conn));
+        }
+
+        let client = Client::open(target.info)?;
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(CONNECTION_TIMEOUT)
+            .set_number_of_retries(CONNECTION_RETRIES)
+            .set_max_delay(MAX_RETRY_DELAY_MS);
+        let conn = ConnectionManager::new_with_config(client, config).await?;
+        Ok(RedisConnection::Managed(Box::new(conn)))
+    }
+
+    /// Opens what is needed to reach `info` and points it there, keeping TLS
+    /// on if it was: the local end of an SSH tunnel, or the node the sentinels
+    /// report. Returns None when it is reached directly.
+    async fn open_tunnels(
+        info: &mut ConnectionInfo,
+        ssh: Option<SshConfig>,
+        sentinel: Option<SentinelConfig>,
+    ) -> RedisResult<Option<Tunnels>> {
+        if ssh.is_none() && sentinel.is_none() {
+            return Ok(None);
+        }
+
+        let (ConnectionAddr::Tcp(host, port) | ConnectionAddr::TcpTls { host, port, .. }) =
+            &mut info.addr
+        else {
+            return Err(invalid_config_error("SSH and Sentinel need a TCP address"));
+        };
+        if ssh.is_some() && sentinel.is_some() {
+            return Err(invalid_config_error(
+                "Sentinel connections cannot go through SSH",
+            ));
+        }
+
+        let mut tunnels = Tunnels::default();
+        (*host, *port) = if let Some(ssh) = ssh {
+            let (remote, remote_port) = (host.clone(), *port);
+            let tunnel =
+                tokio::task::spawn_blocking(move || SshTunnel::open(&ssh, &remote, remote_port))
+                    .await
+                    .map_err(io::Error::other)??;
+            let local_addr = tunnel.local_addr();
+            tunnels.ssh = Some(tunnel);
+            (local_addr.ip().to_string(), local_addr.port())
+        } else {
+            let monitor = SentinelMonitor::open(&sentinel.unwrap()).await?;
+            let node = monitor.node();
+            tunnels.sentinel = Some(monitor);
+            node
+        };
+
+        Ok(Some(tunnels))
+    }
+
+    /// Moves connection `id` to each node `changes` reports, reopening it
+    /// there with the database it has selected at the time. A node that
+    /// cannot be reached is tried again until the sentinels report another.
+    fn follow_sentinel(&self, id: i64, mut changes: watch::Receiver<Node>) -> JoinHandle<()> {
+        let connections = self.connections.clone();
+        let targets = self.targets.clone();
+        let reopening = self.reopening.clone();
+
+        tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                let (host, port) = changes.borrow_and_update().clone();
+                let guard = reopening.lock().await;
+
+                let Some(mut target) = targets.read().unwrap().get(&id).cloned() else {
+                    return;
+                };
+                if let ConnectionAddr::Tcp(h, p)
+                | ConnectionAddr::TcpTls {
+                    host: h, port: p, ..
+                } = &mut target.info.addr
+                {
+                    (*h, *p) = (host.clone(), port);
+                }
+
+                match Self::open(target.clone()).await {
+                    Ok(conn) => {
+                        let mut connections = connections.write().unwrap();
+                        let mut targets = targets.write().unwrap();
+                        if targets.contains_key(&id) {
+                            connections.insert(id, conn);
+                            targets.insert(id, target);
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Reconnecting to {}:{} failed: {}", host, port, e);
+                        drop(guard);
+                        tokio::time::sleep(CONNECTION_TIMEOUT).await;
+                        changes.mark_changed();
+                    }
+                }
+            }
+        })
+    }
+
+    /// Connects to `uri`, using database `db` instead of the one in the URI
+    /// when given. With `tls`, or a `rediss://` URI, the connection is made
+    /// over TLS, and with `ssh` it is forwarded through that SSH server. With
+    /// `sentinel` it goes to the node the sentinels report, following it
+    /// through failovers, and the URI only supplies credentials and the
+    /// database. With TLS, the node's certificate has to be valid for the
+    /// `server_name` in `tls`, or else for the address the sentinels report.
+    /// SSH connections go through a local tunnel, so their certificate is
+    /// checked against the server name or 127.0.0.1.
+    pub async fn connect(
+        &self,
+        id: i64,
+        uri: &str,
+        db: Option<i64>,
+        tls: Option<&TlsConfig>,
+        ssh: Option<&SshConfig>,
+        sentinel: Option<&SentinelConfig>,
+    ) -> RedisResult<()> {
+        let mut target = connection_info(uri, tls)?;
+        if let Some(db) = db {
+            target.info.redis.db = db;
+        }
+
+        let mut tunnel =
+            Self::open_tunnels(&mut target.info, ssh.cloned(), sentinel.cloned()).await?;
+        let conn = Self::open(target.clone()).await?;
+
+        if let Some(tunnel) = &mut tunnel {
+            if let Some(monitor) = &tunnel.sentinel {
+                tunnel.follower = Some(self.follow_sentinel(id, monitor.changes()));
+            }
+        }
+
+        self.connections.write().unwrap().insert(id, conn);
+        self.targets.write().unwrap().insert(id, target);
+
+        let mut tunnels = self.tunnels.lock().unwrap();
+        match tunnel {
+            Some(tunnel) => tunnels.insert(id, tunnel),
+            None => tunnels.remove(&id),
+        };
+        Ok(())
+    }
+
+    pub fn disconnect(&self, id: i64) {
+        self.targets.write().unwrap().remove(&id);
+        self.connections.write().unwrap().remove(&id);
+        self.undo.lock().unwrap().remove(&id);
+        self.tunnels.lock().unwrap().remove(&id);
+    }
+
+    pub fn set_read_only(&self, id: i64, read_only: bool) {
+        let mut ids = self.read_only.write().unwrap();
+        if read_only {
+            ids.insert(id);
+        } else {
+            ids.remove(&id);
+        }
+    }
+
+    pub fn is_read_only(&self, id: i64) -> bool {
+        self.read_only.read().unwrap().contains(&id)
+    }
+
+    /// The connection for a command that writes, failing on read-only
+    /// connections.
+    fn get_writable_connection(&self, id: i64) -> RedisResult<RedisConnection> {
+        if self.is_read_only(id) {
+            return Err(read_only_error());
+        }
+
+        self.get_connection(id)
+    }
+
+    /// The current state of `key`, to keep for undo and digest for the audit
+    /// log.
+    pub async fn snapshot(&self, id: i64, key: &str) -> RedisResult<KeySnapshot> {
+        let mut conn = self.get_connection(id)?;
+        take_snapshot(&mut conn, key).await
+    }
+
+    /// Runs `edit` with `snapshot` standing in for the one the edit would
+    /// take of the same key, so the key is only dumped once.
+    pub async fn with_snapshot<F: Future>(
+        &self,
+        id: i64,
+        snapshot: KeySnapshot,
+        edit: F,
+    ) -> F::Output {
+        TAKEN_SNAPSHOT
+            .scope(RefCell::new(Some((id, snapshot))), edit)
+            .await
+    }
+
+    /// The connection for an edit of `key`, along with the key's current state
+    /// so the edit can be undone.
+    async fn begin_edit(&self, id: i64, key: &str) -> RedisResult<(RedisConnection, KeySnapshot)> {
+        let mut conn = self.get_writable_connection(id)?;
+
+        let taken = TAKEN_SNAPSHOT
+            .try_with(|taken| {
+                taken
+                    .borrow_mut()
+                    .take_if(|(taken_id, snapshot)| *taken_id == id && snapshot.key == key)
+            })
+            .ok()
+            .flatten();
+
+        let snapshot = match taken {
+            Some((_, snapshot)) => snapshot,
+            None => take_snapshot(&mut conn, key).await?,
+        };
+        Ok((conn, snapshot))
+    }
+
+    /// Keeps `snapshot` for undo when the edit succeeded.
+    fn finish_edit<T>(
+        &self,
+        id: i64,
+        snapshot: KeySnapshot,
+        result: RedisResult<T>,
+    ) -> RedisResult<T> {
+        if result.is_ok() {
+            self.push_undo(id, snapshot);
+        }
+        result
+    }
+
+    /// Keeps `snapshot` on top of the undo history, dropping the oldest
+    /// edits past `UNDO_LIMIT` or `UNDO_BYTES`.
+    fn push_undo(&self, id: i64, snapshot: KeySnapshot) {
+        let mut stacks = self.undo.lock().unwrap();
+        let stack = stacks.entry(id).or_default();
+
+        if snapshot.size() > MAX_UNDO_KEY_BYTES {
+            stack.clear();
+            return;
+        }
+
+        let mut size = stack.iter().map(KeySnapshot::size).sum::<usize>() + snapshot.size();
+        while stack.len() >= UNDO_LIMIT || size > UNDO_BYTES {
+            match stack.pop_front() {
+                Some(oldest) => size -= oldest.size(),
+                None => break,
+            }
+        }
+        stack.push_back(snapshot);
+    }
+
+    /// The key `undo_last` would restore next.
+    pub fn undo_key(&self, id: i64) -> Option<String> {
+        self.undo
+            .lock()
+            .unwrap()
+            .get(&id)
+            .and_then(|stack| stack.back())
+            .map(|snapshot| snapshot.key.clone())
+    }
+
+    /// Puts the key changed by the latest edit on connection `id` back the way
+    /// it was, with the TTL it had then, and returns its name. Returns None
+    /// when there is nothing left to undo.
+    pub async fn undo_last(&self, id: i64) -> RedisResult<Option<String>> {
+        let mut conn = self.get_writable_connection(id)?;
+
+        let Some(snapshot) = self
+            .undo
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .and_then(|stack| stack.pop_back())
+        else {
+            return Ok(None);
+        };
+
+        let result: RedisResult<()> = match &snapshot.dump {
+            Some(dump) => {
+                redis::cmd("RESTORE")
+                    .arg(&snapshot.key)
+                    .arg(snapshot.pttl.max(0))
+                    .arg(dump.as_slice())
+                    .arg("REPLACE")
+                    .query_async(&mut conn)
+                    .await
+            }
+            None => conn.del(&snapshot.key).await,
+        };
+
+        match result {
+            Ok(()) => Ok(Some(snapshot.key)),
+            Err(e) => {
+                self.push_undo(id, snapshot);
+                Err(e)
+            }
+        }
+    }
+
+    /// The connection `id`. One whose SSH tunnel has closed, because the
+    /// server ended the session, is dropped instead, keeping its undo
+    /// history, so connecting again opens a new tunnel.
+    pub fn get_connection(&self, id: i64) -> RedisResult<RedisConnection> {
+        {
+            let mut tunnels = self.tunnels.lock().unwrap();
+            let closed = tunnels
+                .get(&id)
+                .and_then(|tunnel| tunnel.ssh.as_ref())
+                .is_some_and(SshTunnel::is_closed);
+            if closed {
+                self.targets.write().unwrap().remove(&id);
+                self.connections.write().unwrap().remove(&id);
+                tunnels.remove(&id);
+                return Err(tunnel_closed_error());
+            }
+        }
+
+        self.connections
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(connection_not_found_error)
+    }
+
+    pub fn selected_db(&self, id: i64) -> RedisResult<i64> {
+        self.targets
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(|target| target.info.redis.db)
+            .ok_or_else(connection_not_found_error)
+    }
+
+    /// Switches connection `id` to database `db` by reconnecting, so every
+    /// later command on it, from any view, works in that database. The undo
+    /// history is dropped because it refers to keys in the previous one.
+    pub async fn select_db(&self, id: i64, db: i64) -> RedisResult<()> {
+        let _reopening = self.reopening.lock().await;
+        let mut target = self
+            .targets
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(connection_not_found_error)?;
+
+        if target.info.redis.db == db {
+            return Ok(());
+        }
+
+        target.info.redis.db = db;
+        let conn = Self::open(target.clone()).await?;
+
+        self.connections.write().unwrap().insert(id, conn);
+        self.targets.write().unwrap().insert(id, target);
+        self.undo.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    /// Key counts of every database that holds keys, from INFO keyspace.
+    pub async fn keyspace(&self, id: i64) -> RedisResult<Vec<RedisKeyspace>> {
+        let mut conn = self.get_connection(id)?;
+
+        let info: String = redis::cmd("INFO")
+            .arg("keyspace")
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(parse_keyspace(&info))
+    }
+
+    pub async fn get_keys(
+        &self,
+        id: i64,
+        pattern: &str,
+        cancel: &CancellationToken,
+    ) -> RedisResult<Vec<String>> {
+        let mut conn = self.get_connection(id)?;
+
+        let mut cursor = 0;
+        let mut keys = HashSet::new();
+
+        loop {
+            if cancel.is_cancelled() {
+                return Err(cancelled_error());
+            }
+
+            let (next_cursor, mut batch): (i64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(100)
+                .query_async(&mut conn)
+                .await?;
+
+            keys.extend(batch.drain(..));
+            cursor = next_cursor;
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        Ok(keys.into_iter().collect())
+    }
+
+    pub async fn scan_keys(
+        &self,
+        id: i64,
+        pattern: &str,
+        cursor: u64,
+        count: usize,
+    ) -> RedisResult<RedisKeyPage> {
+        let mut conn = self.get_connection(id)?;
+
+        let mut cursor = cursor;
+        let mut keys = Vec::new();
+
+        // SCAN may return empty batches, keep going until the page is filled
+        // or the iteration is complete.
+        loop {
+            let (next_cursor, mut batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(count)
+                .query_async(&mut conn)
+                .await?;
+
+            keys.append(&mut batch);
+            cursor = next_cursor;
+
+            if cursor == 0 || keys.len() >= count {
+                break;
+            }
+        }
+
+        Ok(RedisKeyPage { cursor, keys })
+    }
+
+    pub async fn scan_keys_streaming<F>(
+        &self,
+        id: i64,
+        pattern: &str,
+        count: usize,
+        cancel: &CancellationToken,
+        mut on_event: F,
+    ) -> RedisResult<()>
+    where
+        F: FnMut(ScanEvent),
+    {
+        let mut conn = self.get_connection(id)?;
+
+        let total: u64 = redis::cmd("DBSIZE").query_async(&mut conn).await?;
+
+        let mut cursor = 0;
+        let mut scanned = 0;
+        let mut seen = HashSet::new();
+
+        loop {
+            if cancel.is_cancelled() {
+                on_event(ScanEvent::Cancelled { scanned, total });
+                return Ok(());
+            }
+
+            let (next_cursor, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(count)
+                .query_async(&mut conn)
+                .await?;
+
+            cursor = next_cursor;
+
+            // SCAN does not report how many keys it visited, COUNT is the
+            // closest estimate we have.
+            scanned = (scanned + count as u64).min(total);
+
+            let keys: Vec<String> = batch
+                .into_iter()
+                .filter(|key| seen.insert(key.clone()))
+                .collect();
+
+            if !keys.is_empty() {
+                on_event(ScanEvent::Batch {
+                    keys,
+                    scanned,
+                    total,
+                });
+            }
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        on_event(ScanEvent::Finished {
+            scanned: total,
+            total,
+        });
+
+        Ok(())
+    }
+
+    /// How many keys match `pattern`, with the first `sample_size` of them
+    /// found sorted by name. Only the sample is kept while scanning, so the
+    /// count includes the few keys SCAN can return twice while the keyspace
+    /// is resized.
+    pub async fn preview_pattern(
+        &self,
+        id: i64,
+        pattern: &str,
+        sample_size: usize,
+        cancel: &CancellationToken,
+    ) -> RedisResult<RedisKeyPreview> {
+        let mut conn = self.get_connection(id)?;
+
+        let mut cursor = 0;
+        let mut count = 0;
+        let mut sample = Vec::new();
+
+        loop {
+            if cancel.is_cancelled() {
+                return Err(cancelled_error());
+            }
+
+            let (next_cursor, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(100)
+                .query_async(&mut conn)
+                .await?;
+
+            count += batch.len() as u64;
+            for key in batch {
+                if sample.len() < sample_size && !sample.contains(&key) {
+                    sample.push(key);
+                }
+            }
+            cursor = next_cursor;
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        sample.sort();
+
+        Ok(RedisKeyPreview { count, sample })
+    }
+
+    /// UNLINKs every key matching `pattern` in batches of `batch_size` as the
+    /// SCAN finds them, reporting progress after each batch and how it ended,
+    /// errors included. Returns the keys that were deleted, also when it was
+    /// cancelled or failed part way. Bulk deletes are not kept for undo.
+    pub async fn delete_by_pattern<F>(
+        &self,
+        id: i64,
+        pattern: &str,
+        batch_size: usize,
+        cancel: &CancellationToken,
+        mut on_event: F,
+    ) -> Vec<String>
+    where
+        F: FnMut(DeleteEvent),
+    {
+        let mut deleted = Vec::new();
+
+        let result = self
+            .delete_batches(id, pattern, batch_size, cancel, &mut deleted, &mut on_event)
+            .await;
+        if let Err(e) = result {
+            on_event(DeleteEvent::Error {
+                message: e.to_string(),
+                deleted: deleted.len() as u64,
+            });
+        }
+
+        deleted
+    }
+
+    async fn delete_batches<F>(
+        &self,
+        id: i64,
+        pattern: &str,
+        batch_size: usize,
+        cancel: &CancellationToken,
+        deleted: &mut Vec<String>,
+        on_event: &mut F,
+    ) -> RedisResult<()>
+    where
+        F: FnMut(DeleteEvent),
+    {
+        let mut conn = self.get_writable_connection(id)?;
+        let batch_size = batch_size.max(1);
+
+        let total: u64 = redis::cmd("DBSIZE").query_async(&mut conn).await?;
+
+        let mut cursor = 0;
+        let mut scanned = 0;
+        let mut pending: Vec<String> = Vec::new();
+
+        loop {
+            if cancel.is_cancelled() {
+                on_event(DeleteEvent::Cancelled {
+                    deleted: deleted.len() as u64,
+                });
+                return Ok(());
+            }
+
+            let (next_cursor, mut batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(batch_size)
+                .query_async(&mut conn)
+                .await?;
+
+            cursor = next_cursor;
+            scanned = (scanned + batch_size as u64).min(total);
+            pending.append(&mut batch);
+
+            // One UNLINK per key tells which keys were removed, so keys SCAN
+            // returns twice or that expired meanwhile are not counted.
+            while pending.len() >= batch_size || (cursor == 0 && !pending.is_empty()) {
+                let chunk: Vec<String> = pending.drain(..pending.len().min(batch_size)).collect();
+
+                let mut pipe = redis::pipe();
+                pipe.atomic();
+                for key in &chunk {
+                    pipe.unlink(key);
+                }
+                let removed: Vec<bool> = pipe.query_async(&mut conn).await?;
+
+                deleted.extend(
+                    chunk
+                        .into_iter()
+                        .zip(removed)
+                        .filter_map(|(key, removed)| removed.then_some(key)),
+                );
+                on_event(DeleteEvent::Progress {
+                    deleted: deleted.len() as u64,
+                    scanned,
+                    total,
+                });
+            }
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        on_event(DeleteEvent::Finished {
+            deleted: deleted.len() as u64,
+        });
+
+        Ok(())
+    }
+
+    /// Applies `change` to every key matching `pattern`, one pipelined batch
+    /// per SCAN page of `batch_size`. Keys deleted since the SCAN saw them are
+    /// counted as missing, and keys the command left alone (PERSIST on a key
+    /// without TTL, or an unmet `condition`) as skipped. When cancelled, the
+    /// report covers the batches applied so far. A change that would expire
+    /// the keys at once is refused. Not kept for undo.
+    pub async fn set_ttl_by_pattern(
+        &self,
+        id: i64,
+        pattern: &str,
+        change: TtlChange,
+        condition: Option<TtlCondition>,
+        batch_size: usize,
+        cancel: &CancellationToken,
+    ) -> RedisResult<RedisTtlReport> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        check_ttl_change(change, now)?;
+
+        let mut conn = self.get_writable_connection(id)?;
+
+        let mut cursor = 0;
+        let mut seen = HashSet::new();
+        let mut report = RedisTtlReport::default();
+
+        loop {
+            if cancel.is_cancelled() {
+                report.cancelled = true;
+                break;
+            }
+
+            let (next_cursor, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(batch_size.max(1))
+                .query_async(&mut conn)
+                .await?;
+
+            cursor = next_cursor;
+
+            let keys: Vec<String> = batch
+                .into_iter()
+                .filter(|key| seen.insert(key.clone()))
+                .collect();
+
+            if !keys.is_empty() {
+                let mut pipe = redis::pipe();
+                pipe.atomic();
+                for key in &keys {
+                    pipe.exists(key)
+                        .add_command(ttl_command(key, change, condition));
+                }
+
+                let replies: Vec<(bool, bool)> = pipe.query_async(&mut conn).await?;
+                for (key, (exists, changed)) in keys.into_iter().zip(replies) {
+                    match (exists, changed) {
+                        (false, _) => report.missing += 1,
+                        (true, true) => {
+                            report.changed += 1;
+                            report.changed_keys.push(key);
+                        }
+                        (true, false) => report.skipped += 1,
+                    }
+                }
+            }
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+
+    pub async fn get_key_info(&self, id: i64, key: &str) -> RedisResult<Option<RedisKeyValue>> {
+        let mut conn = self.get_connection(id)?;
+
+        let exists: bool = conn.exists(key).await?;
+        if !exists {
+            return Ok(None);
+        }
+
+        let key_type: String = conn.key_type(key).await?;
+
+        let ttl: i64 = conn.ttl(key).await?;
+
+        let value = match key_type.as_str() {
+            "string" => conn.get(key).await?,
+            "list" => {
+                let values: Vec<RedisValue> = conn.lrange(key, 0, -1).await?;
+                to_json_value(&values)
+            }
+            "set" => {
+                let values: Vec<RedisValue> = conn.smembers(key).await?;
+                to_json_value(&values)
+            }
+            "hash" => {
+                let values: Vec<(RedisValue, RedisValue)> = conn.hgetall(key).await?;
+                to_json_value(&values)
+            }
+            "zset" => {
+                let values: Vec<(RedisValue, f64)> = conn.zrange_withscores(key, 0, -1).await?;
+                to_json_value(&values)
+            }
+            "stream" => {
+                let values: Vec<(String, Vec<(RedisValue, RedisValue)>)> = redis::cmd("XRANGE")
+                    .arg(key)
+                    .arg("-")
+                    .arg("+")
+                    .query_async(&mut conn)
+                    .await?;
+                let entries: Vec<RedisStreamEntry> = values
+                    .into_iter()
+                    .map(|(id, fields)| RedisStreamEntry { id, fields })
+                    .collect();
+                to_json_value(&entries)
+            }
+            _ => RedisValue::from("Unsupported type"),
+        };
+
+        Ok(Some(RedisKeyValue {
+            key: key.to_string(),
+            value,
+            ttl,
+            data_type: key_type,
+        }))
+    }
+
+    pub async fn get_value_page(
+        &self,
+        id: i64,
+        key: &str,
+        cursor: u64,
+        count: usize,
+    ) -> RedisResult<Option<RedisValuePage>> {
+        if count == 0 {
+            return Err(RedisError::from((
+                redis::ErrorKind::ClientError,
+                "Page size must be at least 1",
+            )));
+        }
+
+        let mut conn = self.get_connection(id)?;
+
+        let key_type: String = conn.key_type(key).await?;
+
+        let (total, cursor, slice) = match key_type.as_str() {
+            "none" => return Ok(None),
+            "list" => {
+                let total: u64 = conn.llen(key).await?;
+                let start = cursor as isize;
+                let stop = start + count as isize - 1;
+                let values: Vec<RedisValue> = conn.lrange(key, start, stop).await?;
+
+                let next = cursor + count as u64;
+                let cursor = if next >= total { 0 } else { next };
+
+                (total, cursor, RedisValueSlice::List(values))
+            }
+            "set" => {
+                let total: u64 = conn.scard(key).await?;
+                let (cursor, values) =
+                    scan_collection(&mut conn, "SSCAN", key, cursor, count).await?;
+
+                (total, cursor, RedisValueSlice::Set(values))
+            }
+            "hash" => {
+                let total: u64 = conn.hlen(key).await?;
+                let (cursor, values) =
+                    scan_collection(&mut conn, "HSCAN", key, cursor, count).await?;
+
+                (total, cursor, RedisValueSlice::Hash(values))
+            }
+            "zset" => {
+                let total: u64 = conn.zcard(key).await?;
+                let (cursor, values) =
+                    scan_collection(&mut conn, "ZSCAN", key, cursor, count).await?;
+
+                (total, cursor, RedisValueSlice::Zset(values))
+            }
+            _ => return Err(unsupported_type_error(&key_type)),
+        };
+
+        Ok(Some(RedisValuePage {
+            key: key.to_string(),
+            total,
+            cursor,
+            slice,
+        }))
+    }
+
+    /// Reads `count` stream entries starting at `start` (inclusive, or
+    /// exclusive when prefixed with `(`), newest first when `reverse` is set.
+    pub async fn get_stream_range(
+        &self,
+        id: i64,
+        key: &str,
+        start: Option<&str>,
+        count: usize,
+        reverse: bool,
+    ) -> RedisResult<RedisStreamPage> {
+        let mut conn = self.get_connection(id)?;
+
+        let total: u64 = conn.xlen(key).await?;
+
+        let (command, start, end) = if reverse {
+            ("XREVRANGE", start.unwrap_or("+"), "-")
+        } else {
+            ("XRANGE", start.unwrap_or("-"), "+")
+        };
+
+        let values: Vec<(String, Vec<(RedisValue, RedisValue)>)> = redis::cmd(command)
+            .arg(key)
+            .arg(start)
+            .arg(end)
+            .arg("COUNT")
+            .arg(count)
+            .query_async(&mut conn)
+            .await?;
+
+        let next = match values.last() {
+            Some((last_id, _)) if values.len() == count => Some(format!("({}", last_id)),
+            _ => None,
+        };
+
+        let entries = values
+            .into_iter()
+            .map(|(id, fields)| RedisStreamEntry { id, fields })
+            .collect();
+
+        Ok(RedisStreamPage {
+            key: key.to_string(),
+            total,
+            entries,
+            next,
+        })
+    }
+
+    pub async fn add_stream_entry(
+        &self,
+        id: i64,
+        key: &str,
+        entry_id: Option<&str>,
+        fields: &[(RedisValue, RedisValue)],
+    ) -> RedisResult<String> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+        let result = conn.xadd(key, entry_id.unwrap_or("*"), fields).await;
+        self.finish_edit(id, snapshot, result)
+    }
+
+    pub async fn delete_stream_entries(
+        &self,
+        id: i64,
+        key: &str,
+        entry_ids: &[String],
+    ) -> RedisResult<u64> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+        let result = conn.xdel(key, entry_ids).await;
+        self.finish_edit(id, snapshot, result)
+    }
+
+    pub async fn trim_stream(
+        &self,
+        id: i64,
+        key: &str,
+        trim: &StreamTrim,
+        approximate: bool,
+    ) -> RedisResult<u64> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+        let operator = if approximate { "~" } else { "=" };
+
+        let mut cmd = redis::cmd("XTRIM");
+        match trim {
+            StreamTrim::MaxLen(len) => cmd.arg(key).arg("MAXLEN").arg(operator).arg(len),
+            StreamTrim::MinId(min_id) => cmd.arg(key).arg("MINID").arg(operator).arg(min_id),
+        };
+
+        let result = cmd.query_async(&mut conn).await;
+        self.finish_edit(id, snapshot, result)
+    }
+
+    pub async fn get_stream_groups(
+        &self,
+        id: i64,
+        key: &str,
+    ) -> RedisResult<Vec<RedisStreamGroup>> {
+        let mut conn = self.get_connection(id)?;
+
+        let reply: StreamInfoGroupsReply = conn.xinfo_groups(key).await?;
+
+        Ok(reply
+            .groups
+            .into_iter()
+            .map(|group| RedisStreamGroup {
+                name: group.name,
+                consumers: group.consumers,
+                pending: group.pending,
+                last_delivered_id: group.last_delivered_id,
+                lag: group.lag,
+            })
+            .collect())
+    }
+
+    pub async fn get_stream_consumers(
+        &self,
+        id: i64,
+        key: &str,
+        group: &str,
+    ) -> RedisResult<Vec<RedisStreamConsumer>> {
+        let mut conn = self.get_connection(id)?;
+
+        let reply: StreamInfoConsumersReply = conn.xinfo_consumers(key, group).await?;
+
+        Ok(reply
+            .consumers
+            .into_iter()
+            .map(|consumer| RedisStreamConsumer {
+                name: consumer.name,
+                pending: consumer.pending,
+                idle: consumer.idle,
+            })
+            .collect())
+    }
+
+    /// Lists pending entries of `group` from `start` on, optionally only the
+    /// ones owned by `consumer`.
+    pub async fn get_stream_pending(
+        &self,
+        id: i64,
+        key: &str,
+        group: &str,
+        start: Option<&str>,
+        count: usize,
+        consumer: Option<&str>,
+    ) -> RedisResult<Vec<RedisStreamPendingEntry>> {
+        let mut conn = self.get_connection(id)?;
+        let start = start.unwrap_or("-");
+
+        let reply: StreamPendingCountReply = match consumer {
+            Some(consumer) => {
+                conn.xpending_consumer_count(key, group, start, "+", count, consumer)
+                    .await?
+            }
+            None => conn.xpending_count(key, group, start, "+", count).await?,
+        };
+
+        Ok(reply
+            .ids
+            .into_iter()
+            .map(|pending| RedisStreamPendingEntry {
+                id: pending.id,
+                consumer: pending.consumer,
+                idle: pending.last_delivered_ms,
+                delivered: pending.times_delivered,
+            })
+            .collect())
+    }
+
+    pub async fn set_hash_field(
+        &self,
+        id: i64,
+        key: &str,
+        field: &RedisValue,
+        value: &RedisValue,
+    ) -> RedisResult<RedisHashField> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let (value,): (Option<RedisValue>,) = redis::pipe()
+            .atomic()
+            .hset(key, field, value)
+            .ignore()
+            .hget(key, field)
+            .query_async(&mut conn)
+            .await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisHashField {
+            field: field.clone(),
+            value,
+        })
+    }
+
+    pub async fn delete_hash_field(
+        &self,
+        id: i64,
+        key: &str,
+        field: &RedisValue,
+    ) -> RedisResult<RedisHashField> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let (value,): (Option<RedisValue>,) = redis::pipe()
+            .atomic()
+            .hdel(key, field)
+            .ignore()
+            .hget(key, field)
+            .query_async(&mut conn)
+            .await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisHashField {
+            field: field.clone(),
+            value,
+        })
+    }
+
+    pub async fn set_list_element(
+        &self,
+        id: i64,
+        key: &str,
+        index: i64,
+        value: &RedisValue,
+    ) -> RedisResult<RedisListElement> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let (value,): (Option<RedisValue>,) = redis::pipe()
+            .atomic()
+            .lset(key, index as isize, value)
+            .ignore()
+            .lindex(key, index as isize)
+            .query_async(&mut conn)
+            .await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisListElement { index, value })
+    }
+
+    pub async fn push_list_element(
+        &self,
+        id: i64,
+        key: &str,
+        value: &RedisValue,
+        end: ListEnd,
+    ) -> RedisResult<RedisListElement> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let index = match end {
+            ListEnd::Head => {
+                let _: i64 = conn.lpush(key, value).await?;
+                0
+            }
+            ListEnd::Tail => {
+                let len: i64 = conn.rpush(key, value).await?;
+                len - 1
+            }
+        };
+        self.push_undo(id, snapshot);
+
+        Ok(RedisListElement {
+            index,
+            value: Some(value.clone()),
+        })
+    }
+
+    /// Removes `count` occurrences of `value` (all of them when 0, from the
+    /// tail when negative) and returns how many were removed.
+    pub async fn remove_list_element(
+        &self,
+        id: i64,
+        key: &str,
+        value: &RedisValue,
+        count: i64,
+    ) -> RedisResult<u64> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+        let result = conn.lrem(key, count as isize, value).await;
+        self.finish_edit(id, snapshot, result)
+    }
+
+    pub async fn add_set_member(
+        &self,
+        id: i64,
+        key: &str,
+        member: &RedisValue,
+    ) -> RedisResult<RedisSetMember> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let (exists,): (bool,) = redis::pipe()
+            .atomic()
+            .sadd(key, member)
+            .ignore()
+            .sismember(key, member)
+            .query_async(&mut conn)
+            .await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisSetMember {
+            member: member.clone(),
+            exists,
+        })
+    }
+
+    pub async fn remove_set_member(
+        &self,
+        id: i64,
+        key: &str,
+        member: &RedisValue,
+    ) -> RedisResult<RedisSetMember> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let (exists,): (bool,) = redis::pipe()
+            .atomic()
+            .srem(key, member)
+            .ignore()
+            .sismember(key, member)
+            .query_async(&mut conn)
+            .await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisSetMember {
+            member: member.clone(),
+            exists,
+        })
+    }
+
+    pub async fn add_zset_member(
+        &self,
+        id: i64,
+        key: &str,
+        member: &RedisValue,
+        score: f64,
+    ) -> RedisResult<RedisZsetMember> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let (score,): (Option<f64>,) = redis::pipe()
+            .atomic()
+            .zadd(key, member, score)
+            .ignore()
+            .zscore(key, member)
+            .query_async(&mut conn)
+            .await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisZsetMember {
+            member: member.clone(),
+            score,
+        })
+    }
+
+    pub async fn increment_zset_member(
+        &self,
+        id: i64,
+        key: &str,
+        member: &RedisValue,
+        delta: f64,
+    ) -> RedisResult<RedisZsetMember> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let score: f64 = conn.zincr(key, member, delta).await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisZsetMember {
+            member: member.clone(),
+            score: Some(score),
+        })
+    }
+
+    pub async fn remove_zset_member(
+        &self,
+        id: i64,
+        key: &str,
+        member: &RedisValue,
+    ) -> RedisResult<RedisZsetMember> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let (score,): (Option<f64>,) = redis::pipe()
+            .atomic()
+            .zrem(key, member)
+            .ignore()
+            .zscore(key, member)
+            .query_async(&mut conn)
+            .await?;
+        self.push_undo(id, snapshot);
+
+        Ok(RedisZsetMember {
+            member: member.clone(),
+            score,
+        })
+    }
+
+    /// Creates `key` from `payload`. The value is built under a temporary name
+    /// and only renamed into place once every command building it succeeded,
+    /// since EXEC does not roll back a command that fails, such as an XADD with
+    /// an ID below the previous one. Other clients never see a partial key and
+    /// an existing key is only replaced when `overwrite` is set.
+    pub async fn create_key(
+        &self,
+        id: i64,
+        key: &str,
+        payload: &RedisKeyPayload,
+        ttl: Option<i64>,
+        overwrite: bool,
+    ) -> RedisResult<()> {
+        if payload.is_empty() {
+            return Err(RedisError::from((
+                redis::ErrorKind::ClientError,
+                "Cannot create an empty key",
+            )));
+        }
+
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let tmp_key = format!("__setui_create__:{}:{}", key, nanos);
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        match payload {
+            RedisKeyPayload::String(value) => pipe.set(&tmp_key, value).ignore(),
+            RedisKeyPayload::List(values) => pipe.rpush(&tmp_key, values).ignore(),
+            RedisKeyPayload::Set(members) => pipe.sadd(&tmp_key, members).ignore(),
+            RedisKeyPayload::Hash(fields) => pipe.hset_multiple(&tmp_key, fields).ignore(),
+            RedisKeyPayload::Zset(members) => {
+                let members: Vec<(f64, &RedisValue)> = members
+                    .iter()
+                    .map(|(member, score)| (*score, member))
+                    .collect();
+                pipe.zadd_multiple(&tmp_key, &members).ignore()
+            }
+            RedisKeyPayload::Stream(entries) => {
+                for entry in entries {
+                    pipe.xadd(&tmp_key, &entry.id, &entry.fields).ignore();
+                }
+                &mut pipe
+            }
+        };
+
+        if let Some(ttl) = ttl.filter(|ttl| *ttl > 0) {
+            pipe.expire(&tmp_key, ttl).ignore();
+        }
+
+        let mut place = redis::pipe();
+        place.atomic();
+        if overwrite {
+            place.rename(&tmp_key, key);
+        } else {
+            place.rename_nx(&tmp_key, key);
+        }
+        place.del(&tmp_key).ignore();
+
+        // An error reply to any queued command fails the whole query.
+        let result: RedisResult<(bool,)> = match pipe.query_async::<()>(&mut conn).await {
+            Ok(()) => place.query_async(&mut conn).await,
+            Err(e) => Err(e),
+        };
+        let (created,) = match result {
+            Ok(created) => created,
+            Err(e) => {
+                let _: RedisResult<()> = conn.del(&tmp_key).await;
+                return Err(e);
+            }
+        };
+
+        if !created {
+            return Err(key_exists_error());
+        }
+        self.push_undo(id, snapshot);
+
+        Ok(())
+    }
+
+    pub async fn set_key(&self, id: i64, key: &str, value: &RedisValue) -> RedisResult<()> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+        let result = conn.set(key, value).await;
+        self.finish_edit(id, snapshot, result)
+    }
+
+    /// Renames `key`, with RENAMENX unless `conflict` is Replace. Returns false
+    /// when skipped because `new_key` exists.
+    pub async fn rename_key(
+        &self,
+        id: i64,
+        key: &str,
+        new_key: &str,
+        conflict: ConflictPolicy,
+    ) -> RedisResult<bool> {
+        let mut conn = self.get_writable_connection(id)?;
+
+        if let ConflictPolicy::Replace = conflict {
+            let _: () = conn.rename(key, new_key).await?;
+            return Ok(true);
+        }
+
+        let renamed: bool = conn.rename_nx(key, new_key).await?;
+        resolve_conflict(renamed, conflict)
+    }
+
+    /// Copies `key` to `destination`, in logical database `db` when given.
+    /// Returns false when skipped because `destination` exists.
+    pub async fn copy_key(
+        &self,
+        id: i64,
+        key: &str,
+        destination: &str,
+        db: Option<i64>,
+        conflict: ConflictPolicy,
+    ) -> RedisResult<bool> {
+        let mut conn = self.get_writable_connection(id)?;
+
+        let mut copy = redis::cmd("COPY");
+        copy.arg(key).arg(destination);
+        if let Some(db) = db {
+            copy.arg("DB").arg(db);
+        }
+        if let ConflictPolicy::Replace = conflict {
+            copy.arg("REPLACE");
+        }
+
+        // COPY answers 0 both for a missing source and an existing target.
+        let (exists, copied): (bool, bool) = redis::pipe()
+            .atomic()
+            .exists(key)
+            .add_command(copy)
+            .query_async(&mut conn)
+            .await?;
+
+        if !exists {
+            return Err(no_such_key_error());
+        }
+
+        resolve_conflict(copied, conflict)
+    }
+
+    /// Moves `key` to connection `target_id` as `target_key` with DUMP and
+    /// RESTORE, keeping its TTL, then deletes the original if it still holds
+    /// the value that was copied. When it changed in between, the original is
+    /// kept and the copy removed again, unless it replaced a key. Returns false
+    /// when skipped because `target_key` exists. Both servers need compatible
+    /// RDB versions for RESTORE to accept the payload.
+    pub async fn move_key(
+        &self,
+        id: i64,
+        key: &str,
+        target_id: i64,
+        target_key: &str,
+        conflict: ConflictPolicy,
+    ) -> RedisResult<bool> {
+        if id == target_id {
+            return Err(RedisError::from((
+                redis::ErrorKind::ClientError,
+                "Source and target connections are the same",
+            )));
+        }
+
+        let mut source = self.get_writable_connection(id)?;
+        let mut target = self.get_writable_connection(target_id)?;
+
+        let (dump, pttl): (Option<Vec<u8>>, i64) = redis::pipe()
+            .atomic()
+            .cmd("DUMP")
+            .arg(key)
+            .pttl(key)
+            .query_async(&mut source)
+            .await?;
+        let dump = dump.ok_or_else(no_such_key_error)?;
+
+        let mut restore = redis::cmd("RESTORE");
+        restore.arg(target_key).arg(pttl.max(0)).arg(&dump);
+        if let ConflictPolicy::Replace = conflict {
+            restore.arg("REPLACE");
+        }
+
+        match restore.query_async::<()>(&mut target).await {
+            Ok(()) => {}
+            Err(e) if e.code() == Some("BUSYKEY") => return resolve_conflict(false, conflict),
+            Err(e) => return Err(e),
+        }
+
+        let deleted: bool = redis::Script::new(DELETE_UNCHANGED_SCRIPT)
+            .key(key)
+            .arg(&dump)
+            .invoke_async(&mut source)
+            .await?;
+        if !deleted {
+            if !matches!(conflict, ConflictPolicy::Replace) {
+                let _: () = target.del(target_key).await?;
+            }
+            return Err(RedisError::from((
+                redis::ErrorKind::ResponseError,
+                "Key changed while it was being moved",
+            )));
+        }
+
+        Ok(true)
+    }
+
+    pub async fn command_kind(&self, id: i64, args: &[Vec<u8>]) -> RedisResult<CommandKind> {
+        let mut conn = self.get_connection(id)?;
+        command_kind(&mut conn, args).await
+    }
+
+    /// The keys a raw command touches, as reported by COMMAND GETKEYS.
+    pub async fn command_keys(&self, id: i64, args: &[Vec<u8>]) -> RedisResult<Vec<Vec<u8>>> {
+        let mut conn = self.get_connection(id)?;
+
+        let mut cmd = redis::cmd("COMMAND");
+        cmd.arg("GETKEYS");
+        for arg in args {
+            cmd.arg(arg.as_slice());
+        }
+
+        match cmd.query_async(&mut conn).await {
+            Err(e) if e.kind() == redis::ErrorKind::ResponseError => Ok(Vec::new()),
+            result => result,
+        }
+    }
+
+    /// SHA-256 of the key's DUMP payload, or None when it does not exist.
+    pub async fn value_digest(&self, id: i64, key: &[u8]) -> RedisResult<Option<String>> {
+        let mut conn = self.get_connection(id)?;
+
+        let dump: Option<Vec<u8>> = redis::cmd("DUMP").arg(key).query_async(&mut conn).await?;
+        Ok(dump.as_deref().map(dump_digest))
+    }
+
+    /// `value_digest` of a key in database `db`. The database is selected and
+    /// switched back inside one MULTI, so no other command runs in it.
+    pub async fn value_digest_in(
+        &self,
+        id: i64,
+        key: &[u8],
+        db: i64,
+    ) -> RedisResult<Option<String>> {
+        let selected = self.selected_db(id)?;
+        if db == selected {
+            return self.value_digest(id, key).await;
+        }
+
+        let mut conn = self.get_connection(id)?;
+        let (dump,): (Option<Vec<u8>>,) = redis::pipe()
+            .atomic()
+            .cmd("SELECT")
+            .arg(db)
+            .ignore()
+            .cmd("DUMP")
+            .arg(key)
+            .cmd("SELECT")
+            .arg(selected)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(dump.as_deref().map(dump_digest))
+    }
+
+    /// Runs a raw command. Errors sent by the server are part of the reply,
+    /// only connection and client errors fail the call. Read-only connections
+    /// reject admin commands as well as writes.
+    pub async fn execute_command(&self, id: i64, args: &[Vec<u8>]) -> RedisResult<RedisReply> {
+        let mut conn = self.get_connection(id)?;
+
+        if self.is_read_only(id) {
+            let kind = command_kind(&mut conn, args).await?;
+            if kind.writes || kind.admin {
+                return Err(read_only_error());
+            }
+        }
+
+        let mut cmd = redis::Cmd::new();
+        for arg in args {
+            cmd.arg(arg.as_slice());
+        }
+
+        match cmd.query_async::<redis::Value>(&mut conn).await {
+            Ok(value) => Ok(value.into()),
+            Err(e) => console::error_reply(e),
+        }
+    }
+
+    pub async fn delete_key(&self, id: i64, key: &str) -> RedisResult<()> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+        let result = conn.del(key).await;
+        self.finish_edit(id, snapshot, result)
+    }
+
+    pub async fn set_ttl(&self, id: i64, key: &str, ttl: i64) -> RedisResult<()> {
+        let (mut conn, snapshot) = self.begin_edit(id, key).await?;
+
+        if ttl < 0 {
+            let _: () = conn.persist(key).await?;
+        } else {
+            let _: bool = conn.expire(key, ttl).await?;
+        }
+        self.push_undo(id, snapshot);
+
+        Ok(())
+    }
+}
+
+/// Renders a whole collection as JSON for `RedisKeyValue`, each element carrying
+/// its own encoding so binary members survive.
+fn to_json_value<T: Serialize>(values: &T) -> RedisValue {
+    RedisValue::from(serde_json::to_string(values).unwrap_or_default().as_str())
+}
+
+/// Runs `command` (SSCAN, HSCAN or ZSCAN) until at least `count` elements are
+/// collected or the iteration is complete.
+async fn scan_collection<T: redis::FromRedisValue>(
+    conn: &mut RedisConnection,
+    command: &str,
+    key: &str,
+    cursor: u64,
+    count: usize,
+) -> RedisResult<(u64, Vec<T>)> {
+    let mut cursor = cursor;
+    let mut values = Vec::new();
+
+    loop {
+        let (next_cursor, mut batch): (u64, Vec<T>) = redis::cmd(command)
+            .arg(key)
+            .arg(cursor)
+            .arg("COUNT")
+            .arg(count)
+            .query_async(conn)
+            .await?;
+
+        values.append(&mut batch);
+        cursor = next_cursor;
+
+        if cursor == 0 || values.len() >= count {
+            break;
+        }
+    }
+
+    Ok((cursor, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::ValueEncoding;
+    use serial_test::serial;
+
+    const TEST_REDIS_URI: &str = "redis://127.0.0.1:6379/";
+    /// A redis-server with TLS and client certificates required, using the
+    /// certificates in tests/tls. Its certificate is only issued for
+    /// redis.test, so connecting to it takes that server name.
+    const TEST_TLS_URI: &str = "redis://127.0.0.1:6380/";
+    /// An sshd allowing TCP forwarding, sharing the host network with the
+    /// redis servers above. User setui logs in with password setui or the key
+    /// in tests/ssh.
+    const TEST_SSH_HOST: &str = "127.0.0.1";
+    const TEST_SSH_PORT: u16 = 2222;
+    /// A sentinel monitoring the redis server above as mymaster.
+    const TEST_SENTINEL_ADDRESS: &str = "127.0.0.1:26379";
+    const TEST_ID: i64 = 1;
+
+    async fn setup() -> RedisManager {
+        let manager = RedisManager::new();
+        manager
+            .connect(TEST_ID, TEST_REDIS_URI, None, None, None, None)
+            .await
+            .unwrap();
+        manager
+    }
+
+    async fn cleanup(manager: &RedisManager) {
+        if let Ok(mut conn) = manager.get_connection(TEST_ID) {
+            let _: () = redis::cmd("FLUSHDB").query_async(&mut conn).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn connection() {
+        let manager = RedisManager::new();
+        assert!(manager
+            .connect(TEST_ID, TEST_REDIS_URI, None, None, None, None)
+            .await
+            .is_ok());
+        assert!(manager.get_connection(TEST_ID).is_ok());
+
+        manager.disconnect(TEST_ID);
+        assert!(manager.get_connection(TEST_ID).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn set_get_string() {
+        let manager = setup().await;
+
+        assert!(manager
+            .set_key(TEST_ID, "test_key", &"test_value".into())
+            .await
+            .is_ok());
+
+        let info = manager
+            .get_key_info(TEST_ID, "test_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.key, "test_key");
+        assert_eq!(info.value, RedisValue::from("test_value"));
+        assert_eq!(info.data_type, "string");
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ttl() {
+        let manager = setup().await;
+
+        manager
+            .set_key(TEST_ID, "ttl_key", &"value".into())
+            .await
+            .unwrap();
+        manager.set_ttl(TEST_ID, "ttl_key", 100).await.unwrap();
+
+        let info = manager
+            .get_key_info(TEST_ID, "ttl_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(info.ttl > 0 && info.ttl <= 100);
+
+        manager.set_ttl(TEST_ID, "ttl_key", -1).await.unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "ttl_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.ttl, -1);
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn delete_key() {
+        let manager = setup().await;
+
+        manager
+            .set_key(TEST_ID, "delete_key", &"value".into())
+            .await
+            .unwrap();
+        assert!(manager
+            .get_key_info(TEST_ID, "delete_key")
+            .await
+            .unwrap()
+            .is_some());
+
+        manager.delete_key(TEST_ID, "delete_key").await.unwrap();
+        assert!(manager
+            .get_key_info(TEST_ID, "delete_key")
+            .await
+            .unwrap()
+            .is_none());
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn get_keys() {
+        let manager = setup().await;
+
+        manager
+            .set_key(TEST_ID, "key1", &"value1".into())
+            .await
+            .unwrap();
+        manager
+            .set_key(TEST_ID, "key2", &"value2".into())
+            .await
+            .unwrap();
+
+        let keys = manager
+            .get_keys(TEST_ID, "key*", &CancellationToken::default())
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&"key1".to_string()));
+        assert!(keys.contains(&"key2".to_string()));
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn scan_keys() {
+        let manager = setup().await;
+
+        for i in 0..25 {
+            manager
+                .set_key(TEST_ID, &format!("page:{}", i), &"value".into())
+                .await
+                .unwrap();
+        }
+
+        let mut cursor = 0;
+        let mut keys = HashSet::new();
+
+        loop {
+            let page = manager
+                .scan_keys(TEST_ID, "page:*", cursor, 10)
+                .await
+                .unwrap();
+            keys.extend(page.keys);
+            cursor = page.cursor;
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        assert_eq!(keys.len(), 25);
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn scan_keys_streaming() {
+        let manager = setup().await;
+
+        for i in 0..25 {
+            manager
+                .set_key(TEST_ID, &format!("stream:{}", i), &"value".into())
+                .await
+                .unwrap();
+        }
+        manager
+            .set_key(TEST_ID, "other", &"value".into())
+            .await
+            .unwrap();
+
+        let mut keys = Vec::new();
+        let mut finished = false;
+
+        manager
+            .scan_keys_streaming(
+                TEST_ID,
+                "stream:*",
+                10,
+                &CancellationToken::default(),
+                |event| match event {
+                    ScanEvent::Batch { keys: batch, .. } => keys.extend(batch),
+                    ScanEvent::Finished { scanned, total } => {
+                        assert_eq!(scanned, total);
+                        assert_eq!(total, 26);
+                        finished = true;
+                    }
+                    _ => unreachable!(),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(finished);
+        assert_eq!(keys.len(), 25);
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn cancelled_scan() {
+        let manager = setup().await;
+
+        manager
+            .set_key(TEST_ID, "key1", &"value1".into())
+            .await
+            .unwrap();
+
+        let cancel = CancellationToken::default();
+        cancel.cancel();
+
+        assert!(manager.get_keys(TEST_ID, "key*", &cancel).await.is_err());
+
+        let mut cancelled = false;
+        manager
+            .scan_keys_streaming(TEST_ID, "key*", 10, &cancel, |event| {
+                cancelled = matches!(event, ScanEvent::Cancelled { .. });
+            })
+            .await
+            .unwrap();
+        assert!(cancelled);
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn shared_connection() {
+        let manager = setup().await;
+
+        let mut first = manager.get_connection(TEST_ID).unwrap();
+        let mut second = manager.get_connection(TEST_ID).unwrap();
+
+        let first_id: i64 = redis::cmd("CLIENT")
+            .arg("ID")
+            .query_async(&mut first)
+            .await
+            .unwrap();
+        let second_id: i64 = redis::cmd("CLIENT")
+            .arg("ID")
+            .query_async(&mut second)
+            .await
+            .unwrap();
+        assert_eq!(first_id, second_id);
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn concurrent_connections() {
+        let manager = setup().await;
+        manager
+            .connect(TEST_ID + 1, TEST_REDIS_URI, None, None, None, None)
+            .await
+            .unwrap();
+
+        let write = |i: i64| {
+            let manager = &manager;
+            async move {
+                let id = TEST_ID + i % 2;
+                let key = format!("concurrent:{}", i);
+                manager.set_key(id, &key, &"value".into()).await.unwrap();
+                assert!(manager.get_key_info(id, &key).await.unwrap().is_some());
+            }
+        };
+        tokio::join!(write(0), write(1), write(2), write(3));
+
+        let keys = manager
+            .get_keys(TEST_ID, "concurrent:*", &CancellationToken::default())
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 4);
+
+        manager.disconnect(TEST_ID + 1);
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn value_pages() {
+        let manager = setup().await;
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+
+        let values: Vec<RedisValue> = (0..25)
+            .map(|i| RedisValue::from(format!("value{}", i).as_str()))
+            .collect();
+        let _: () = conn.rpush("list_key", &values).await.unwrap();
+
+        let page = manager
+            .get_value_page(TEST_ID, "list_key", 0, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.total, 25);
+        assert_eq!(page.cursor, 10);
+        match page.slice {
+            RedisValueSlice::List(items) => assert_eq!(items, values[0..10]),
+            _ => panic!("expected a list slice"),
+        }
+
+        let page = manager
+            .get_value_page(TEST_ID, "list_key", 20, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.cursor, 0);
+        match page.slice {
+            RedisValueSlice::List(items) => assert_eq!(items, values[20..25]),
+            _ => panic!("expected a list slice"),
+        }
+
+        for i in 0..25 {
+            let _: () = conn
+                .hset("hash_key", format!("field{}", i), i)
+                .await
+                .unwrap();
+        }
+
+        let mut cursor = 0;
+        let mut fields = HashMap::new();
+        loop {
+            let page = manager
+                .get_value_page(TEST_ID, "hash_key", cursor, 10)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(page.total, 25);
+            match page.slice {
+                RedisValueSlice::Hash(items) => fields.extend(items),
+                _ => panic!("expected a hash slice"),
+            }
+
+            cursor = page.cursor;
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(fields.len(), 25);
+        assert_eq!(fields[&RedisValue::from("field7")], RedisValue::from("7"));
+
+        let _: () = conn.zadd("zset_key", "member", 1.5).await.unwrap();
+        let page = manager
+            .get_value_page(TEST_ID, "zset_key", 0, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.total, 1);
+        match page.slice {
+            RedisValueSlice::Zset(items) => assert_eq!(items, vec![("member".into(), 1.5)]),
+            _ => panic!("expected a zset slice"),
+        }
+
+        assert!(manager
+            .get_value_page(TEST_ID, "missing", 0, 10)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(manager
+            .get_value_page(TEST_ID, "zset_key", 0, 0)
+            .await
+            .is_err());
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn streams() {
+        let manager = setup().await;
+        let fields = vec![(RedisValue::from("field"), RedisValue::from("value"))];
+
+        let mut ids = Vec::new();
+        for i in 1..=5 {
+            let entry_id = format!("{}-0", i);
+            ids.push(
+                manager
+                    .add_stream_entry(TEST_ID, "stream_key", Some(&entry_id), &fields)
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(ids[0], "1-0");
+
+        let info = manager
+            .get_key_info(TEST_ID, "stream_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.data_type, "stream");
+        assert!(info.value.encode().contains("5-0"));
+
+        let page = manager
+            .get_stream_range(TEST_ID, "stream_key", None, 3, false)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(page.entries.len(), 3);
+        assert_eq!(page.entries[0].fields, fields);
+        assert_eq!(page.next.as_deref(), Some("(3-0"));
+
+        let page = manager
+            .get_stream_range(TEST_ID, "stream_key", page.next.as_deref(), 3, false)
+            .await
+            .unwrap();
+        assert_eq!(page.entries.len(), 2);
+        assert_eq!(page.entries[0].id, "4-0");
+        assert!(page.next.is_none());
+
+        let page = manager
+            .get_stream_range(TEST_ID, "stream_key", None, 2, true)
+            .await
+            .unwrap();
+        assert_eq!(page.entries[0].id, "5-0");
+        assert_eq!(page.entries[1].id, "4-0");
+
+        let deleted = manager
+            .delete_stream_entries(TEST_ID, "stream_key", &["1-0".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+
+        let trimmed = manager
+            .trim_stream(TEST_ID, "stream_key", &StreamTrim::MaxLen(2), false)
+            .await
+            .unwrap();
+        assert_eq!(trimmed, 2);
+
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+        let _: () = conn
+            .xgroup_create("stream_key", "group", "0")
+            .await
+            .unwrap();
+        let _: redis::Value = redis::cmd("XREADGROUP")
+            .arg("GROUP")
+            .arg("group")
+            .arg("consumer")
+            .arg("COUNT")
+            .arg(1)
+            .arg("STREAMS")
+            .arg("stream_key")
+            .arg(">")
+            .query_async(&mut conn)
+            .await
+            .unwrap();
+
+        let groups = manager
+            .get_stream_groups(TEST_ID, "stream_key")
+            .await
+            .unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "group");
+        assert_eq!(groups[0].pending, 1);
+
+        let consumers = manager
+            .get_stream_consumers(TEST_ID, "stream_key", "group")
+            .await
+            .unwrap();
+        assert_eq!(consumers[0].name, "consumer");
+
+        let pending = manager
+            .get_stream_pending(TEST_ID, "stream_key", "group", None, 10, None)
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "4-0");
+        assert_eq!(pending[0].consumer, "consumer");
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn element_mutations() {
+        let manager = setup().await;
+
+        let field = manager
+            .set_hash_field(TEST_ID, "hash_key", &"field".into(), &"value".into())
+            .await
+            .unwrap();
+        assert_eq!(field.value, Some("value".into()));
+        let field = manager
+            .delete_hash_field(TEST_ID, "hash_key", &"field".into())
+            .await
+            .unwrap();
+        assert!(field.value.is_none());
+
+        let element = manager
+            .push_list_element(TEST_ID, "list_key", &"b".into(), ListEnd::Tail)
+            .await
+            .unwrap();
+        assert_eq!(element.index, 0);
+        let element = manager
+            .push_list_element(TEST_ID, "list_key", &"c".into(), ListEnd::Tail)
+            .await
+            .unwrap();
+        assert_eq!(element.index, 1);
+        let element = manager
+            .push_list_element(TEST_ID, "list_key", &"a".into(), ListEnd::Head)
+            .await
+            .unwrap();
+        assert_eq!(element.index, 0);
+        let element = manager
+            .set_list_element(TEST_ID, "list_key", 2, &"z".into())
+            .await
+            .unwrap();
+        assert_eq!(element.value, Some("z".into()));
+        let removed = manager
+            .remove_list_element(TEST_ID, "list_key", &"a".into(), 0)
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+
+        let member = manager
+            .add_set_member(TEST_ID, "set_key", &"member".into())
+            .await
+            .unwrap();
+        assert!(member.exists);
+        let member = manager
+            .remove_set_member(TEST_ID, "set_key", &"member".into())
+            .await
+            .unwrap();
+        assert!(!member.exists);
+
+        let member = manager
+            .add_zset_member(TEST_ID, "zset_key", &"member".into(), 1.0)
+            .await
+            .unwrap();
+        assert_eq!(member.score, Some(1.0));
+        let member = manager
+            .increment_zset_member(TEST_ID, "zset_key", &"member".into(), 2.5)
+            .await
+            .unwrap();
+        assert_eq!(member.score, Some(3.5));
+        let member = manager
+            .remove_zset_member(TEST_ID, "zset_key", &"member".into())
+            .await
+            .unwrap();
+        assert!(member.score.is_none());
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn create_key() {
+        let manager = setup().await;
+
+        let payload = RedisKeyPayload::Hash(vec![("field".into(), "value".into())]);
+        manager
+            .create_key(TEST_ID, "created_hash", &payload, Some(100), false)
+            .await
+            .unwrap();
+
+        let info = manager
+            .get_key_info(TEST_ID, "created_hash")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.data_type, "hash");
+        assert!(info.value.encode().contains("field"));
+        assert!(info.ttl > 0 && info.ttl <= 100);
+
+        let payload = RedisKeyPayload::Zset(vec![("member".into(), 2.0)]);
+        assert!(manager
+            .create_key(TEST_ID, "created_hash", &payload, None, false)
+            .await
+            .is_err());
+
+        manager
+            .create_key(TEST_ID, "created_hash", &payload, None, true)
+            .await
+            .unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "created_hash")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.data_type, "zset");
+        assert_eq!(info.ttl, -1);
+
+        let payload = RedisKeyPayload::Stream(vec![RedisStreamEntry {
+            id: "*".to_string(),
+            fields: vec![("field".into(), "value".into())],
+        }]);
+        manager
+            .create_key(TEST_ID, "created_stream", &payload, None, false)
+            .await
+            .unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "created_stream")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.data_type, "stream");
+
+        let payload = RedisKeyPayload::Stream(
+            ["2-0", "1-0"]
+                .into_iter()
+                .map(|id| RedisStreamEntry {
+                    id: id.to_string(),
+                    fields: vec![("field".into(), "value".into())],
+                })
+                .collect(),
+        );
+        assert!(manager
+            .create_key(TEST_ID, "created_stream", &payload, None, true)
+            .await
+            .is_err());
+        let page = manager
+            .get_stream_range(TEST_ID, "created_stream", None, 10, false)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+
+        assert!(manager
+            .create_key(
+                TEST_ID,
+                "empty",
+                &RedisKeyPayload::List(vec![]),
+                None,
+                false
+            )
+            .await
+            .is_err());
+
+        let keys = manager
+            .get_keys(TEST_ID, "*", &CancellationToken::default())
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 2);
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn binary_values() {
+        let manager = setup().await;
+
+        let value = RedisValue::decode(ValueEncoding::Hex, "0a03666f6f10ff01").unwrap();
+        manager
+            .set_key(TEST_ID, "binary_key", &value)
+            .await
+            .unwrap();
+
+        let info = manager
+            .get_key_info(TEST_ID, "binary_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.value.encoding, ValueEncoding::Base64);
+        assert_eq!(info.value.bytes, value.bytes);
+
+        let element = manager
+            .push_list_element(TEST_ID, "binary_list", &value, ListEnd::Tail)
+            .await
+            .unwrap();
+        let page = manager
+            .get_value_page(TEST_ID, "binary_list", 0, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        match page.slice {
+            RedisValueSlice::List(items) => {
+                assert_eq!(items[0].bytes, element.value.unwrap().bytes)
+            }
+            _ => panic!("expected a list slice"),
+        }
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn execute_command() {
+        let manager = setup().await;
+
+        let run = |line: &str| {
+            let args = console::tokenize(line).unwrap();
+            let manager = &manager;
+            async move { manager.execute_command(TEST_ID, &args).await.unwrap() }
+        };
+
+        assert_eq!(
+            run(r#"SET "my key" "a b""#).await,
+            RedisReply::SimpleString("OK".to_string())
+        );
+        assert_eq!(
+            run(r#"GET "my key""#).await,
+            RedisReply::BulkString("a b".into())
+        );
+        assert_eq!(run("GET missing").await, RedisReply::Nil);
+        assert_eq!(run("RPUSH list 1 2").await, RedisReply::Integer(2));
+        assert_eq!(
+            run("LRANGE list 0 -1").await,
+            RedisReply::Array(vec![
+                RedisReply::BulkString("1".into()),
+                RedisReply::BulkString("2".into())
+            ])
+        );
+        assert!(matches!(
+            run("GET list").await,
+            RedisReply::Error(message) if message.starts_with("WRONGTYPE")
+        ));
+        assert!(matches!(run("NOSUCHCOMMAND").await, RedisReply::Error(_)));
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn read_only_connection() {
+        let manager = setup().await;
+        manager
+            .set_key(TEST_ID, "ro_key", &"before".into())
+            .await
+            .unwrap();
+
+        manager.set_read_only(TEST_ID, true);
+
+        assert!(manager
+            .set_key(TEST_ID, "ro_key", &"after".into())
+            .await
+            .is_err());
+        assert!(manager.delete_key(TEST_ID, "ro_key").await.is_err());
+        let mut failed = false;
+        let deleted = manager
+            .delete_by_pattern(
+                TEST_ID,
+                "ro_*",
+                10,
+                &CancellationToken::default(),
+                |event| failed = matches!(event, DeleteEvent::Error { deleted: 0, .. }),
+            )
+            .await;
+        assert!(deleted.is_empty());
+        assert!(failed);
+        assert!(manager
+            .add_set_member(TEST_ID, "ro_set", &"member".into())
+            .await
+            .is_err());
+
+        let write = console::tokenize("SET ro_key after").unwrap();
+        assert!(manager.execute_command(TEST_ID, &write).await.is_err());
+
+        let read = console::tokenize("GET ro_key").unwrap();
+        assert_eq!(
+            manager.execute_command(TEST_ID, &read).await.unwrap(),
+            RedisReply::BulkString("before".into())
+        );
+
+        for command in [
+            "FUNCTION FLUSH",
+            "FUNCTION DELETE ro_lib",
+            "XGROUP DESTROY ro_stream ro_group",
+            "SCRIPT FLUSH",
+            "CONFIG SET maxmemory-policy noeviction",
+            "SHUTDOWN ABORT",
+            "REPLICAOF NO ONE",
+            "ACL SETUSER ro_user",
+            "DEBUG SLEEP 0",
+            "CLIENT KILL ID 0",
+        ] {
+            let args = console::tokenize(command).unwrap();
+            assert!(
+                manager.execute_command(TEST_ID, &args).await.is_err(),
+                "{} was not rejected",
+                command
+            );
+        }
+
+        let kind = manager
+            .command_kind(TEST_ID, &console::tokenize("XGROUP DESTROY s g").unwrap())
+            .await
+            .unwrap();
+        assert!(kind.writes);
+        let kind = manager
+            .command_kind(TEST_ID, &console::tokenize("XINFO STREAM s").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(kind, CommandKind::default());
+
+        manager.set_read_only(TEST_ID, false);
+        assert!(manager.delete_key(TEST_ID, "ro_key").await.is_ok());
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn command_keys_and_digest() {
+        let manager = setup().await;
+
+        let keys = manager
+            .command_keys(TEST_ID, &console::tokenize("DEL a b").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert!(manager
+            .command_keys(TEST_ID, &console::tokenize("FLUSHDB").unwrap())
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(manager
+            .value_digest(TEST_ID, b"digest_key")
+            .await
+            .unwrap()
+            .is_none());
+        manager
+            .set_key(TEST_ID, "digest_key", &"one".into())
+            .await
+            .unwrap();
+        let first = manager.value_digest(TEST_ID, b"digest_key").await.unwrap();
+        manager
+            .set_key(TEST_ID, "digest_key", &"two".into())
+            .await
+            .unwrap();
+        let second = manager.value_digest(TEST_ID, b"digest_key").await.unwrap();
+        assert!(first.as_ref().is_some_and(|digest| digest.len() == 64));
+        assert_ne!(first, second);
+
+        assert_eq!(
+            manager
+                .value_digest_in(TEST_ID, b"digest_key", 0)
+                .await
+                .unwrap(),
+            second
+        );
+        assert!(manager
+            .value_digest_in(TEST_ID, b"digest_key", 1)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            manager.value_digest(TEST_ID, b"digest_key").await.unwrap(),
+            second
+        );
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn undo_edits() {
+        let manager = setup().await;
+        assert_eq!(manager.undo_last(TEST_ID).await.unwrap(), None);
+
+        manager
+            .set_key(TEST_ID, "undo_key", &"first".into())
+            .await
+            .unwrap();
+        manager
+            .set_key(TEST_ID, "undo_key", &"second".into())
+            .await
+            .unwrap();
+        manager.set_ttl(TEST_ID, "undo_key", 100).await.unwrap();
+        manager
+            .set_hash_field(TEST_ID, "undo_hash", &"field".into(), &"value".into())
+            .await
+            .unwrap();
+        manager.delete_key(TEST_ID, "undo_key").await.unwrap();
+
+        assert_eq!(manager.undo_key(TEST_ID).as_deref(), Some("undo_key"));
+        assert_eq!(
+            manager.undo_last(TEST_ID).await.unwrap().as_deref(),
+            Some("undo_key")
+        );
+        let info = manager
+            .get_key_info(TEST_ID, "undo_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.value, "second".into());
+        assert!(info.ttl > 0);
+
+        manager.undo_last(TEST_ID).await.unwrap();
+        assert!(manager
+            .get_key_info(TEST_ID, "undo_hash")
+            .await
+            .unwrap()
+            .is_none());
+
+        manager.undo_last(TEST_ID).await.unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "undo_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.ttl, -1);
+
+        manager.undo_last(TEST_ID).await.unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "undo_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.value, "first".into());
+        assert_eq!(info.ttl, -1);
+
+        manager.undo_last(TEST_ID).await.unwrap();
+        assert!(manager
+            .get_key_info(TEST_ID, "undo_key")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(manager.undo_last(TEST_ID).await.unwrap(), None);
+
+        let snapshot = manager.snapshot(TEST_ID, "undo_key").await.unwrap();
+        assert_eq!(snapshot.digest(), None);
+        let value = RedisValue::from("taken");
+        let edit = manager.set_key(TEST_ID, "undo_key", &value);
+        manager
+            .with_snapshot(TEST_ID, snapshot, edit)
+            .await
+            .unwrap();
+        manager.undo_last(TEST_ID).await.unwrap();
+        assert!(manager
+            .get_key_info(TEST_ID, "undo_key")
+            .await
+            .unwrap()
+            .is_none());
+
+        cleanup(&manager).await;
+    }
+
+    #[test]
+    fn undo_budget() {
+        let manager = RedisManager::default();
+        let snapshot = |key: &str, size: usize| KeySnapshot {
+            key: key.to_string(),
+            dump: Some(vec![0; size]),
+            pttl: -1,
+        };
+
+        for i in 0..UNDO_LIMIT + 1 {
+            manager.push_undo(TEST_ID, snapshot(&i.to_string(), 1));
+        }
+        assert_eq!(manager.undo.lock().unwrap()[&TEST_ID].len(), UNDO_LIMIT);
+
+        let count = UNDO_BYTES / MAX_UNDO_KEY_BYTES;
+        for i in 0..count {
+            manager.push_undo(TEST_ID, snapshot(&i.to_string(), MAX_UNDO_KEY_BYTES));
+        }
+        assert_eq!(manager.undo.lock().unwrap()[&TEST_ID].len(), count);
+        manager.push_undo(TEST_ID, snapshot("last", 1));
+        assert_eq!(manager.undo.lock().unwrap()[&TEST_ID].len(), count);
+        assert_eq!(manager.undo_key(TEST_ID).as_deref(), Some("last"));
+
+        manager.push_undo(TEST_ID, snapshot("large", MAX_UNDO_KEY_BYTES + 1));
+        assert_eq!(manager.undo_key(TEST_ID), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn bulk_delete() {
+        let manager = setup().await;
+
+        for i in 0..30 {
+            manager
+                .set_key(TEST_ID, &format!("bulk:{}", i), &"value".into())
+                .await
+                .unwrap();
+        }
+        manager
+            .set_key(TEST_ID, "keep", &"value".into())
+            .await
+            .unwrap();
+
+        let preview = manager
+            .preview_pattern(TEST_ID, "bulk:*", 5, &CancellationToken::default())
+            .await
+            .unwrap();
+        assert_eq!(preview.count, 30);
+        assert_eq!(preview.sample.len(), 5);
+        assert!(preview.sample.iter().all(|key| key.starts_with("bulk:")));
 
-impl RedisManager {
-    pub fn new() -> Self {
-        RedisManager {
-            connections: HashMap::new(),
+        let cancel = CancellationToken::default();
+        cancel.cancel();
+        let deleted = manager
+            .delete_by_pattern(TEST_ID, "bulk:*", 7, &cancel, |event| {
+                assert!(matches!(event, DeleteEvent::Cancelled { deleted: 0 }))
+            })
+            .await;
+        assert!(deleted.is_empty());
+
+        let mut progress = Vec::new();
+        let mut finished = None;
+        let mut deleted = manager
+            .delete_by_pattern(
+                TEST_ID,
+                "bulk:*",
+                7,
+                &CancellationToken::default(),
+                |event| match event {
+                    DeleteEvent::Progress { deleted, .. } => progress.push(deleted),
+                    DeleteEvent::Finished { deleted } => finished = Some(deleted),
+                    _ => unreachable!(),
+                },
+            )
+            .await;
+
+        let mut expected: Vec<String> = (0..30).map(|i| format!("bulk:{}", i)).collect();
+        deleted.sort();
+        expected.sort();
+        assert_eq!(deleted, expected);
+        assert_eq!(finished, Some(30));
+        assert!(progress.len() >= 5);
+        assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+        assert!(manager
+            .get_key_info(TEST_ID, "keep")
+            .await
+            .unwrap()
+            .is_some());
+
+        cleanup(&manager).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ttl_by_pattern() {
+        let manager = setup().await;
+
+        for i in 0..10 {
+            let key = format!("session:{}", i);
+            manager
+                .set_key(TEST_ID, &key, &"value".into())
+                .await
+                .unwrap();
+            if i % 2 == 0 {
+                manager.set_ttl(TEST_ID, &key, 100).await.unwrap();
+            }
+        }
+        manager
+            .set_key(TEST_ID, "other", &"value".into())
+            .await
+            .unwrap();
+
+        let cancel = CancellationToken::default();
+        let report = manager
+            .set_ttl_by_pattern(
+                TEST_ID,
+                "session:*",
+                TtlChange::Expire(500),
+                Some(TtlCondition::Nx),
+                4,
+                &cancel,
+            )
+            .await
+            .unwrap();
+        let mut changed_keys = report.changed_keys;
+        changed_keys.sort();
+        assert_eq!(
+            changed_keys,
+            vec![
+                "session:1",
+                "session:3",
+                "session:5",
+                "session:7",
+                "session:9"
+            ]
+        );
+        assert_eq!(
+            (
+                report.changed,
+                report.skipped,
+                report.missing,
+                report.cancelled
+            ),
+            (5, 5, 0, false)
+        );
+
+        let info = manager
+            .get_key_info(TEST_ID, "session:1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(info.ttl > 100);
+
+        let report = manager
+            .set_ttl_by_pattern(TEST_ID, "session:*", TtlChange::Persist, None, 4, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(report.changed, 10);
+
+        let report = manager
+            .set_ttl_by_pattern(TEST_ID, "session:*", TtlChange::Persist, None, 4, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(report.skipped, 10);
+
+        cancel.cancel();
+        let report = manager
+            .set_ttl_by_pattern(
+                TEST_ID,
+                "session:*",
+                TtlChange::Expire(500),
+                None,
+                4,
+                &cancel,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            report,
+            RedisTtlReport {
+                cancelled: true,
+                ..Default::default()
+            }
+        );
+
+        let info = manager
+            .get_key_info(TEST_ID, "other")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.ttl, -1);
+
+        let cancel = CancellationToken::default();
+        for change in [
+            TtlChange::Expire(0),
+            TtlChange::PExpire(-1),
+            TtlChange::ExpireAt(1),
+        ] {
+            assert!(manager
+                .set_ttl_by_pattern(TEST_ID, "session:*", change, None, 4, &cancel)
+                .await
+                .is_err());
         }
+        assert!(manager
+            .get_key_info(TEST_ID, "session:0")
+            .await
+            .unwrap()
+            .is_some());
+
+        cleanup(&manager).await;
     }
 
-    pub fn connect(&mut self, id: i64, uri: &str) -> RedisResult<()> {
-        let client = Client::open(uri)?;
-        let _: RedisConnection = client.get_connection()?;
-        self.connections.insert(id, client);
-        Ok(())
+    #[test]
+    fn expiry_in_the_past() {
+        let now = 1_700_000_000;
+        assert!(check_ttl_change(TtlChange::Expire(0), now).is_err());
+        assert!(check_ttl_change(TtlChange::PExpire(-5), now).is_err());
+        assert!(check_ttl_change(TtlChange::ExpireAt(now), now).is_err());
+
+        assert!(check_ttl_change(TtlChange::Expire(1), now).is_ok());
+        assert!(check_ttl_change(TtlChange::ExpireAt(now + 1), now).is_ok());
+        assert!(check_ttl_change(TtlChange::Persist, now).is_ok());
     }
 
-    pub fn disconnect(&mut self, id: i64) {
-        self.connections.remove(&id);
+    #[test]
+    fn keyspace_lines() {
+        let info = "# Keyspace\r\ndb0:keys=3,expires=1,avg_ttl=5000\r\ndb12:keys=7,expires=0,avg_ttl=0,subexpiry=0\r\n";
+        assert_eq!(
+            parse_keyspace(info),
+            vec![
+                RedisKeyspace {
+                    db: 0,
+                    keys: 3,
+                    expires: 1,
+                    avg_ttl: 5000
+                },
+                RedisKeyspace {
+                    db: 12,
+                    keys: 7,
+                    expires: 0,
+                    avg_ttl: 0
+                },
+            ]
+        );
     }
 
-    pub fn get_client(&self, id: i64) -> Option<&Client> {
-        self.connections.get(&id)
+    #[tokio::test]
+    #[serial]
+    async fn select_database() {
+        let manager = setup().await;
+        assert_eq!(manager.selected_db(TEST_ID).unwrap(), 0);
+
+        manager
+            .set_key(TEST_ID, "db0", &"zero".into())
+            .await
+            .unwrap();
+        assert!(manager.undo_key(TEST_ID).is_some());
+
+        manager.select_db(TEST_ID, 2).await.unwrap();
+        assert_eq!(manager.selected_db(TEST_ID).unwrap(), 2);
+        assert!(manager.undo_key(TEST_ID).is_none());
+        assert!(manager
+            .get_key_info(TEST_ID, "db0")
+            .await
+            .unwrap()
+            .is_none());
+
+        manager
+            .set_key(TEST_ID, "db2", &"two".into())
+            .await
+            .unwrap();
+        manager.set_ttl(TEST_ID, "db2", 100).await.unwrap();
+
+        let keyspace = manager.keyspace(TEST_ID).await.unwrap();
+        let db2 = keyspace.iter().find(|k| k.db == 2).unwrap();
+        assert_eq!((db2.keys, db2.expires), (1, 1));
+        assert!(keyspace.iter().any(|k| k.db == 0 && k.keys >= 1));
+
+        cleanup(&manager).await;
+        manager.select_db(TEST_ID, 0).await.unwrap();
+        cleanup(&manager).await;
     }
 
-    pub fn get_keys(&self, id: i64, pattern: &str) -> RedisResult<Vec<String>> {
-        let client = self.get_client(id).ok_or(RedisError::from((
-            redis::ErrorKind::ResponseError,
-            "Connection not found",
-        )))?;
-        let mut conn = client.get_connection()?;
+    #[tokio::test]
+    #[serial]
+    async fn rename_copy_move() {
+        let manager = setup().await;
+        let other_id = TEST_ID + 1;
+        manager
+            .connect(other_id, TEST_REDIS_URI, Some(1), None, None, None)
+            .await
+            .unwrap();
 
-        let mut cursor = 0;
-        let mut keys = HashSet::new();
+        manager
+            .set_key(TEST_ID, "src", &"one".into())
+            .await
+            .unwrap();
+        manager
+            .set_key(TEST_ID, "taken", &"two".into())
+            .await
+            .unwrap();
 
-        loop {
-            let (next_cursor, mut batch): (i64, Vec<String>) = redis::cmd("SCAN")
-                .arg(cursor)
-                .arg("MATCH")
-                .arg(pattern)
-                .arg("COUNT")
-                .arg(100)
-                .query(&mut conn)?;
+        assert!(!manager
+            .rename_key(TEST_ID, "src", "taken", ConflictPolicy::Skip)
+            .await
+            .unwrap());
+        assert!(manager
+            .rename_key(TEST_ID, "src", "taken", ConflictPolicy::Fail)
+            .await
+            .is_err());
+        assert!(manager
+            .rename_key(TEST_ID, "src", "renamed", ConflictPolicy::Fail)
+            .await
+            .unwrap());
 
-            keys.extend(batch.drain(..));
-            cursor = next_cursor;
+        assert!(manager
+            .copy_key(TEST_ID, "missing", "copy", None, ConflictPolicy::Fail)
+            .await
+            .is_err());
+        assert!(manager
+            .copy_key(TEST_ID, "renamed", "taken", None, ConflictPolicy::Fail)
+            .await
+            .is_err());
+        assert!(manager
+            .copy_key(TEST_ID, "renamed", "taken", None, ConflictPolicy::Replace)
+            .await
+            .unwrap());
+        assert!(manager
+            .copy_key(TEST_ID, "renamed", "copied", Some(1), ConflictPolicy::Fail)
+            .await
+            .unwrap());
+        let info = manager
+            .get_key_info(other_id, "copied")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.value, "one".into());
 
-            if cursor == 0 {
-                break;
-            }
-        }
+        manager.set_ttl(TEST_ID, "renamed", 100).await.unwrap();
+        assert!(!manager
+            .move_key(TEST_ID, "renamed", other_id, "copied", ConflictPolicy::Skip)
+            .await
+            .unwrap());
+        assert!(manager
+            .move_key(TEST_ID, "renamed", other_id, "moved", ConflictPolicy::Fail)
+            .await
+            .unwrap());
+        assert!(manager
+            .get_key_info(TEST_ID, "renamed")
+            .await
+            .unwrap()
+            .is_none());
+        let info = manager
+            .get_key_info(other_id, "moved")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.value, "one".into());
+        assert!(info.ttl > 0);
 
-        Ok(keys.into_iter().collect())
+        let mut conn = manager.get_connection(other_id).unwrap();
+        let _: () = redis::cmd("FLUSHDB").query_async(&mut conn).await.unwrap();
+        cleanup(&manager).await;
     }
 
-    pub fn get_key_info(&self, id: i64, key: &str) -> RedisResult<Option<RedisKeyValue>> {
-        let client = self.get_client(id).ok_or(RedisError::from((
-            redis::ErrorKind::ResponseError,
-            "Connection not found",
-        )))?;
-        let mut conn = client.get_connection()?;
+    fn tls_fixture(name: &str) -> Option<String> {
+        Some(format!("{}/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name))
+    }
 
-        let exists: bool = conn.exists(key)?;
-        if !exists {
-            return Ok(None);
+    fn test_tls_config() -> TlsConfig {
+        TlsConfig {
+            ca_path: tls_fixture("ca.crt"),
+            client_cert_path: tls_fixture("client.crt"),
+            client_key_path: tls_fixture("client.key"),
+            server_name: Some(String::from("redis.test")),
+            insecure: false,
         }
+    }
 
-        let key_type: String = conn.key_type(key)?;
-
-        let ttl: i64 = conn.ttl(key)?;
+    #[test]
+    fn tls_uri() {
+        let target = connection_info("redis://127.0.0.1:6379/", None).unwrap();
+        assert!(matches!(target.info.addr, ConnectionAddr::Tcp(..)));
+        assert!(target.tls.is_none());
 
-        let value = match key_type.as_str() {
-            "string" => conn.get(key)?,
-            "list" => {
-                let values: Vec<String> = conn.lrange(key, 0, -1)?;
-                serde_json::to_string(&values).unwrap_or_default()
-            }
-            "set" => {
-                let values: Vec<String> = conn.smembers(key)?;
-                serde_json::to_string(&values).unwrap_or_default()
+        let info = connection_info("rediss://:secret@example.com:6380/2", None)
+            .unwrap()
+            .info;
+        assert!(matches!(
+            info.addr,
+            ConnectionAddr::TcpTls {
+                port: 6380,
+                insecure: false,
+                ..
             }
-            "hash" => {
-                let values: HashMap<String, String> = conn.hgetall(key)?;
-                serde_json::to_string(&values).unwrap_or_default()
+        ));
+        assert_eq!(info.redis.db, 2);
+
+        let config = test_tls_config();
+        let target = connection_info("rediss://example.com/#insecure", Some(&config)).unwrap();
+        assert!(matches!(
+            target.info.addr,
+            ConnectionAddr::TcpTls {
+                insecure: true,
+                tls_params: Some(_),
+                ..
             }
-            "zset" => {
-                let values: Vec<(String, f64)> = conn.zrange_withscores(key, 0, -1)?;
-                serde_json::to_string(&values).unwrap_or_default()
+        ));
+        assert!(target.tls.is_none());
+
+        let target = connection_info("redis://example.com/", Some(&config)).unwrap();
+        assert!(matches!(target.info.addr, ConnectionAddr::Tcp(..)));
+        assert!(target.tls.is_some());
+
+        let unnamed = TlsConfig {
+            server_name: None,
+            ..test_tls_config()
+        };
+        let target = connection_info("redis://example.com/", Some(&unnamed)).unwrap();
+        assert!(matches!(
+            target.info.addr,
+            ConnectionAddr::TcpTls {
+                insecure: false,
+                ..
             }
-            _ => String::from("Unsupported type"),
+        ));
+        assert!(target.tls.is_none());
+    }
+
+    #[test]
+    fn tls_config_errors() {
+        let missing_key = TlsConfig {
+            client_key_path: None,
+            ..test_tls_config()
         };
+        assert!(connection_info(TEST_TLS_URI, Some(&missing_key)).is_err());
 
-        Ok(Some(RedisKeyValue {
-            key: key.to_string(),
-            value,
-            ttl,
-            data_type: key_type,
-        }))
-    }
+        let missing_file = TlsConfig {
+            ca_path: tls_fixture("missing.crt"),
+            ..test_tls_config()
+        };
+        assert!(connection_info(TEST_TLS_URI, Some(&missing_file)).is_err());
 
-    pub fn set_key(&self, id: i64, key: &str, value: &str) -> RedisResult<()> {
-        let client = self.get_client(id).ok_or(RedisError::from((
-            redis::ErrorKind::ResponseError,
-            "Connection not found",
-        )))?;
-        let mut conn = client.get_connection()?;
-        conn.set(key, value)
-    }
+        let invalid_name = TlsConfig {
+            server_name: Some(String::from("not a name!")),
+            ..test_tls_config()
+        };
+        assert!(connection_info(TEST_TLS_URI, Some(&invalid_name)).is_err());
 
-    pub fn delete_key(&self, id: i64, key: &str) -> RedisResult<()> {
-        let client = self.get_client(id).ok_or(RedisError::from((
-            redis::ErrorKind::ResponseError,
-            "Connection not found",
-        )))?;
-        let mut conn = client.get_connection()?;
-        conn.del(key)
+        assert!(connection_info("redis+unix:///tmp/redis.sock", Some(&test_tls_config())).is_err());
     }
 
-    pub fn set_ttl(&self, id: i64, key: &str, ttl: i64) -> RedisResult<()> {
-        let client = self.get_client(id).ok_or(RedisError::from((
-            redis::ErrorKind::ResponseError,
-            "Connection not found",
-        )))?;
+    #[tokio::test]
+    #[serial]
+    async fn tls_connection() {
+        let manager = RedisManager::new();
+        manager
+            .connect(
+                TEST_ID,
+                TEST_TLS_URI,
+                None,
+                Some(&test_tls_config()),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
 
-        let mut conn = client.get_connection()?;
+        manager
+            .set_key(TEST_ID, "tls_key", &"over tls".into())
+            .await
+            .unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "tls_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.value, RedisValue::from("over tls"));
 
-        if ttl < 0 {
-            let _: () = conn.persist(key)?;
-        } else {
-            let _: bool = conn.expire(key, ttl)?;
-        }
+        manager.select_db(TEST_ID, 1).await.unwrap();
+        assert!(manager
+            .get_key_info(TEST_ID, "tls_key")
+            .await
+            .unwrap()
+            .is_none());
+        manager.select_db(TEST_ID, 0).await.unwrap();
 
-        Ok(())
+        cleanup(&manager).await;
+        manager.disconnect(TEST_ID);
+        assert!(manager.get_connection(TEST_ID).is_err());
+        assert!(manager.tunnels.lock().unwrap().is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serial_test::serial;
+    #[tokio::test]
+    #[serial]
+    async fn tls_insecure() {
+        let manager = RedisManager::new();
+        let config = TlsConfig {
+            ca_path: None,
+            ..test_tls_config()
+        };
 
-    const TEST_REDIS_URI: &str = "redis://127.0.0.1:6379/";
-    const TEST_ID: i64 = 1;
+        assert!(manager
+            .connect(TEST_ID, TEST_TLS_URI, None, Some(&config), None, None)
+            .await
+            .is_err());
+        assert!(manager.get_connection(TEST_ID).is_err());
 
-    fn setup() -> RedisManager {
-        let mut manager = RedisManager::new();
-        manager.connect(TEST_ID, TEST_REDIS_URI).unwrap();
         manager
+            .connect(
+                TEST_ID,
+                "rediss://127.0.0.1:6380/#insecure",
+                None,
+                Some(&config),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+        let pong: String = redis::cmd("PING").query_async(&mut conn).await.unwrap();
+        assert_eq!(pong, "PONG");
+
+        manager.disconnect(TEST_ID);
     }
 
-    fn cleanup(manager: &RedisManager) {
-        if let Some(client) = manager.get_client(TEST_ID) {
-            let mut conn = client.get_connection().unwrap();
-            let _: () = redis::cmd("FLUSHDB").query(&mut conn).unwrap();
+    #[tokio::test]
+    #[serial]
+    async fn tls_wrong_server_name() {
+        let manager = RedisManager::new();
+        let unnamed = TlsConfig {
+            server_name: None,
+            ..test_tls_config()
+        };
+        let wrong_name = TlsConfig {
+            server_name: Some(String::from("localhost")),
+            ..test_tls_config()
+        };
+
+        for (uri, config) in [
+            ("redis://localhost:6380/", &unnamed),
+            (TEST_TLS_URI, &unnamed),
+            (TEST_TLS_URI, &wrong_name),
+        ] {
+            assert!(manager
+                .connect(TEST_ID, uri, None, Some(config), None, None)
+                .await
+                .is_err());
+            assert!(manager.get_connection(TEST_ID).is_err());
         }
     }
 
-    #[test]
+    /// The test SSH server with its host key confirmed, as the user would.
+    fn test_ssh_config() -> SshConfig {
+        let mut config = SshConfig {
+            host: String::from(TEST_SSH_HOST),
+            port: TEST_SSH_PORT,
+            user: String::from("setui"),
+            password: Some(String::from("setui")),
+            private_key_path: None,
+            passphrase: None,
+            host_key_fingerprint: None,
+        };
+        config.host_key_fingerprint = Some(crate::ssh::host_key_fingerprint(&config).unwrap());
+        config
+    }
+
+    #[tokio::test]
     #[serial]
-    fn connection() {
-        let mut manager = RedisManager::new();
-        assert!(manager.connect(TEST_ID, TEST_REDIS_URI).is_ok());
-        assert!(manager.get_client(TEST_ID).is_some());
+    async fn ssh_tunnel() {
+        let manager = RedisManager::new();
+        manager
+            .connect(
+                TEST_ID,
+                TEST_REDIS_URI,
+                None,
+                None,
+                Some(&test_ssh_config()),
+                None,
+            )
+            .await
+            .unwrap();
+
+        manager
+            .set_key(TEST_ID, "ssh_key", &"through ssh".into())
+            .await
+            .unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "ssh_key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.value, RedisValue::from("through ssh"));
 
+        cleanup(&manager).await;
         manager.disconnect(TEST_ID);
-        assert!(manager.get_client(TEST_ID).is_none());
+        assert!(manager.tunnels.lock().unwrap().is_empty());
     }
 
-    #[test]
+    #[tokio::test]
     #[serial]
-    fn set_get_string() {
-        let manager = setup();
+    async fn ssh_private_key() {
+        let manager = RedisManager::new();
+        let key_path = format!("{}/tests/ssh/id_ecdsa", env!("CARGO_MANIFEST_DIR"));
+        let config = SshConfig {
+            password: None,
+            private_key_path: Some(key_path),
+            passphrase: Some(String::from("setui")),
+            ..test_ssh_config()
+        };
 
-        assert!(manager.set_key(TEST_ID, "test_key", "test_value").is_ok());
+        manager
+            .connect(TEST_ID, TEST_REDIS_URI, None, None, Some(&config), None)
+            .await
+            .unwrap();
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+        let pong: String = redis::cmd("PING").query_async(&mut conn).await.unwrap();
+        assert_eq!(pong, "PONG");
 
-        let info = manager.get_key_info(TEST_ID, "test_key").unwrap().unwrap();
-        assert_eq!(info.key, "test_key");
-        assert_eq!(info.value, "test_value");
-        assert_eq!(info.data_type, "string");
+        manager.disconnect(TEST_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ssh_wrong_password() {
+        let manager = RedisManager::new();
+        let config = SshConfig {
+            password: Some(String::from("wrong")),
+            ..test_ssh_config()
+        };
 
-        cleanup(&manager);
+        assert!(manager
+            .connect(TEST_ID, TEST_REDIS_URI, None, None, Some(&config), None)
+            .await
+            .is_err());
+        assert!(manager.get_connection(TEST_ID).is_err());
+        assert!(manager.tunnels.lock().unwrap().is_empty());
     }
 
-    #[test]
+    #[tokio::test]
     #[serial]
-    fn ttl() {
-        let manager = setup();
+    async fn ssh_host_key() {
+        let manager = RedisManager::new();
 
-        manager.set_key(TEST_ID, "ttl_key", "value").unwrap();
-        manager.set_ttl(TEST_ID, "ttl_key", 100).unwrap();
+        let unconfirmed = SshConfig {
+            host_key_fingerprint: None,
+            ..test_ssh_config()
+        };
+        let error = manager
+            .connect(
+                TEST_ID,
+                TEST_REDIS_URI,
+                None,
+                None,
+                Some(&unconfirmed),
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("SHA256:"));
 
-        let info = manager.get_key_info(TEST_ID, "ttl_key").unwrap().unwrap();
-        assert!(info.ttl > 0 && info.ttl <= 100);
+        let changed = SshConfig {
+            host_key_fingerprint: Some(String::from("SHA256:changed")),
+            ..test_ssh_config()
+        };
+        assert!(manager
+            .connect(TEST_ID, TEST_REDIS_URI, None, None, Some(&changed), None)
+            .await
+            .is_err());
 
-        manager.set_ttl(TEST_ID, "ttl_key", -1).unwrap();
-        let info = manager.get_key_info(TEST_ID, "ttl_key").unwrap().unwrap();
-        assert_eq!(info.ttl, -1);
+        assert!(manager.get_connection(TEST_ID).is_err());
+        assert!(manager.tunnels.lock().unwrap().is_empty());
+    }
 
-        cleanup(&manager);
+    /// Forwards connections to the test SSH server until `kill` cuts them,
+    /// as if the server had gone away.
+    struct SshProxy {
+        port: u16,
+        streams: Arc<Mutex<Vec<std::net::TcpStream>>>,
     }
 
-    #[test]
+    impl SshProxy {
+        fn start() -> Self {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let streams = Arc::new(Mutex::new(Vec::new()));
+            let accepted = streams.clone();
+
+            std::thread::spawn(move || {
+                for client in listener.incoming().flatten() {
+                    let server =
+                        std::net::TcpStream::connect((TEST_SSH_HOST, TEST_SSH_PORT)).unwrap();
+                    accepted
+                        .lock()
+                        .unwrap()
+                        .extend([client.try_clone().unwrap(), server.try_clone().unwrap()]);
+
+                    let upstream = (client.try_clone().unwrap(), server.try_clone().unwrap());
+                    for (mut from, mut to) in [upstream, (server, client)] {
+                        std::thread::spawn(move || {
+                            let _ = std::io::copy(&mut from, &mut to);
+                            let _ = to.shutdown(std::net::Shutdown::Write);
+                        });
+                    }
+                }
+            });
+
+            SshProxy { port, streams }
+        }
+
+        fn kill(&self) {
+            for stream in self.streams.lock().unwrap().drain(..) {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+        }
+    }
+
+    #[tokio::test]
     #[serial]
-    fn delete_key() {
-        let manager = setup();
+    async fn ssh_server_gone() {
+        let manager = RedisManager::new();
+        let proxy = SshProxy::start();
+        let config = SshConfig {
+            port: proxy.port,
+            ..test_ssh_config()
+        };
 
-        manager.set_key(TEST_ID, "delete_key", "value").unwrap();
-        assert!(manager
-            .get_key_info(TEST_ID, "delete_key")
-            .unwrap()
-            .is_some());
+        manager
+            .connect(TEST_ID, TEST_REDIS_URI, None, None, Some(&config), None)
+            .await
+            .unwrap();
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+        let pong: String = redis::cmd("PING").query_async(&mut conn).await.unwrap();
+        assert_eq!(pong, "PONG");
+
+        proxy.kill();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while manager.get_connection(TEST_ID).is_ok() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(manager.tunnels.lock().unwrap().is_empty());
+
+        manager
+            .connect(TEST_ID, TEST_REDIS_URI, None, None, Some(&config), None)
+            .await
+            .unwrap();
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+        let pong: String = redis::cmd("PING").query_async(&mut conn).await.unwrap();
+        assert_eq!(pong, "PONG");
+
+        manager.disconnect(TEST_ID);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn tls_over_ssh() {
+        let manager = RedisManager::new();
+        manager
+            .connect(
+                TEST_ID,
+                TEST_TLS_URI,
+                None,
+                Some(&test_tls_config()),
+                Some(&test_ssh_config()),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+        let pong: String = redis::cmd("PING").query_async(&mut conn).await.unwrap();
+        assert_eq!(pong, "PONG");
 
-        manager.delete_key(TEST_ID, "delete_key").unwrap();
+        manager.select_db(TEST_ID, 1).await.unwrap();
+        assert_eq!(manager.selected_db(TEST_ID).unwrap(), 1);
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+        let pong: String = redis::cmd("PING").query_async(&mut conn).await.unwrap();
+        assert_eq!(pong, "PONG");
+        manager.disconnect(TEST_ID);
+
+        // The tunnel ends at 127.0.0.1, which the certificate is not issued
+        // for.
+        let unnamed = TlsConfig {
+            server_name: None,
+            ..test_tls_config()
+        };
         assert!(manager
-            .get_key_info(TEST_ID, "delete_key")
+            .connect(
+                TEST_ID,
+                TEST_TLS_URI,
+                None,
+                Some(&unnamed),
+                Some(&test_ssh_config()),
+                None,
+            )
+            .await
+            .is_err());
+        assert!(manager.tunnels.lock().unwrap().is_empty());
+    }
+
+    fn test_sentinel_config() -> SentinelConfig {
+        SentinelConfig {
+            addresses: vec![
+                String::from("127.0.0.1:1"),
+                String::from(TEST_SENTINEL_ADDRESS),
+            ],
+            master_name: String::from("mymaster"),
+            username: None,
+            password: None,
+            replica: false,
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn sentinel_master() {
+        let manager = RedisManager::new();
+        manager
+            .connect(
+                TEST_ID,
+                "redis://mymaster/",
+                Some(1),
+                None,
+                None,
+                Some(&test_sentinel_config()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(manager.selected_db(TEST_ID).unwrap(), 1);
+
+        manager
+            .set_key(TEST_ID, "sentinel_key", &"via sentinel".into())
+            .await
+            .unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "sentinel_key")
+            .await
             .unwrap()
-            .is_none());
+            .unwrap();
+        assert_eq!(info.value, RedisValue::from("via sentinel"));
 
-        cleanup(&manager);
+        cleanup(&manager).await;
+        manager.disconnect(TEST_ID);
+        assert!(manager.tunnels.lock().unwrap().is_empty());
     }
 
-    #[test]
+    #[tokio::test]
     #[serial]
-    fn get_keys() {
-        let manager = setup();
+    async fn sentinel_errors() {
+        let manager = RedisManager::new();
 
-        manager.set_key(TEST_ID, "key1", "value1").unwrap();
-        manager.set_key(TEST_ID, "key2", "value2").unwrap();
+        let unknown = SentinelConfig {
+            master_name: String::from("unknown"),
+            ..test_sentinel_config()
+        };
+        assert!(manager
+            .connect(
+                TEST_ID,
+                "redis://unknown/",
+                None,
+                None,
+                None,
+                Some(&unknown)
+            )
+            .await
+            .is_err());
 
-        let keys = manager.get_keys(TEST_ID, "key*").unwrap();
-        assert_eq!(keys.len(), 2);
-        assert!(keys.contains(&"key1".to_string()));
-        assert!(keys.contains(&"key2".to_string()));
+        assert!(manager
+            .connect(
+                TEST_ID,
+                "redis://mymaster/",
+                None,
+                None,
+                Some(&test_ssh_config()),
+                Some(&test_sentinel_config()),
+            )
+            .await
+            .is_err());
 
-        cleanup(&manager);
+        assert!(manager.get_connection(TEST_ID).is_err());
+        assert!(manager.tunnels.lock().unwrap().is_empty());
     }
 
-    #[test]
+    #[tokio::test]
     #[serial]
-    fn complex_types() {
-        let manager = setup();
-        let mut conn = manager
-            .get_client(TEST_ID)
+    async fn complex_types() {
+        let manager = setup().await;
+        let mut conn = manager.get_connection(TEST_ID).unwrap();
+
+        let _: () = conn.lpush("list_key", &["value1", "value2"]).await.unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "list_key")
+            .await
             .unwrap()
-            .get_connection()
             .unwrap();
-
-        let _: () = conn.lpush("list_key", &["value1", "value2"]).unwrap();
-        let info = manager.get_key_info(TEST_ID, "list_key").unwrap().unwrap();
         assert_eq!(info.data_type, "list");
-        assert!(info.value.contains("value1"));
-        assert!(info.value.contains("value2"));
+        assert!(info.value.encode().contains("value1"));
+        assert!(info.value.encode().contains("value2"));
 
-        let _: () = conn.sadd("set_key", &["value1", "value2"]).unwrap();
-        let info = manager.get_key_info(TEST_ID, "set_key").unwrap().unwrap();
+        let _: () = conn.sadd("set_key", &["value1", "value2"]).await.unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "set_key")
+            .await
+            .unwrap()
+            .unwrap();
         assert_eq!(info.data_type, "set");
-        assert!(info.value.contains("value1"));
-        assert!(info.value.contains("value2"));
+        assert!(info.value.encode().contains("value1"));
+        assert!(info.value.encode().contains("value2"));
 
-        let _: () = conn.hset("hash_key", "field1", "value1").unwrap();
-        let info = manager.get_key_info(TEST_ID, "hash_key").unwrap().unwrap();
+        let _: () = conn.hset("hash_key", "field1", "value1").await.unwrap();
+        let info = manager
+            .get_key_info(TEST_ID, "hash_key")
+            .await
+            .unwrap()
+            .unwrap();
         assert_eq!(info.data_type, "hash");
-        assert!(info.value.contains("field1"));
-        assert!(info.value.contains("value1"));
+        assert!(info.value.encode().contains("field1"));
+        assert!(info.value.encode().contains("value1"));
 
-        cleanup(&manager);
+        cleanup(&manager).await;
     }
 }
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncConnectionConfig, Client, IntoConnectionInfo, RedisError, RedisResult};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::ob::SentinelConfig;
+
+const SENTINEL_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the sentinels are asked whether the node has changed.
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Host and port of a Redis server.
+pub type Node = (String, u16);
+
+/// Follows the node Sentinel reports for a master: the master itself, or one
+/// of its replicas. One connection to a sentinel is kept open and asked again
+/// every few seconds, moving on to the other sentinels only when it fails.
+pub struct SentinelMonitor {
+    node: watch::Receiver<Node>,
+    task: JoinHandle<()>,
+}
+
+impl SentinelMonitor {
+    /// Looks the node up and starts following it, failing when no sentinel
+    /// knows the master.
+    pub async fn open(config: &SentinelConfig) -> RedisResult<Self> {
+        let mut sentinel = Sentinel {
+            config: config.clone(),
+            conn: None,
+        };
+        let (sender, node) = watch::channel(sentinel.resolve().await?);
+
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RESOLVE_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match sentinel.resolve().await {
+                    Ok(node) => sentinel.report(&sender, node),
+                    Err(e) => log::error!(
+                        "Sentinel lookup of {} failed: {}",
+                        sentinel.config.master_name,
+                        e
+                    ),
+                }
+            }
+        });
+
+        Ok(SentinelMonitor { node, task })
+    }
+
+    /// The node as of the latest lookup.
+    pub fn node(&self) -> Node {
+        self.node.borrow().clone()
+    }
+
+    /// A receiver that is notified whenever the node changes.
+    pub fn changes(&self) -> watch::Receiver<Node> {
+        self.node.clone()
+    }
+}
+
+impl Drop for SentinelMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The sentinels of one master, with the connection to the one that last
+/// answered.
+struct Sentinel {
+    config: SentinelConfig,
+    conn: Option<MultiplexedConnection>,
+}
+
+impl Sentinel {
+    /// Asks the sentinel connected to, or else each one in turn, returning the
+    /// first answer or the last error.
+    async fn resolve(&mut self) -> RedisResult<Node> {
+        if let Some(conn) = &mut self.conn {
+            match query(&self.config, conn).await {
+                Ok(node) => return Ok(node),
+                Err(e) => {
+                    log::warn!("Sentinel of {} failed: {}", self.config.master_name, e);
+                    self.conn = None;
+                }
+            }
+        }
+
+        let mut last_error =
+            sentinel_error("No sentinel configured", self.config.master_name.clone());
+
+        for address in &self.config.addresses {
+            let answer = match connect(&self.config, address).await {
+                Ok(mut conn) => query(&self.config, &mut conn)
+                    .await
+                    .map(|node| (conn, node)),
+                Err(e) => Err(e),
+            };
+
+            match answer {
+                Ok((conn, node)) => {
+                    self.conn = Some(conn);
+                    return Ok(node);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn report(&self, sender: &watch::Sender<Node>, node: Node) {
+        sender.send_if_modified(|current| {
+            if *current == node {
+                return false;
+            }
+
+            log::info!(
+                "{} moved from {}:{} to {}:{}",
+                self.config.master_name,
+                current.0,
+                current.1,
+                node.0,
+                node.1
+            );
+            *current = node;
+            true
+        });
+    }
+}
+
+fn sentinel_error(message: &'static str, detail: String) -> RedisError {
+    RedisError::from((redis::ErrorKind::ResponseError, message, detail))
+}
+
+async fn connect(config: &SentinelConfig, address: &str) -> RedisResult<MultiplexedConnection> {
+    let mut info = format!("redis://{}", address).into_connection_info()?;
+    info.redis.username = config.username.clone();
+    info.redis.password = config.password.clone();
+
+    let timeouts = AsyncConnectionConfig::new()
+        .set_connection_timeout(SENTINEL_TIMEOUT)
+        .set_response_timeout(SENTINEL_TIMEOUT);
+    Client::open(info)?
+        .get_multiplexed_async_connection_with_config(&timeouts)
+        .await
+}
+
+async fn query(config: &SentinelConfig, conn: &mut MultiplexedConnection) -> RedisResult<Node> {
+    if config.replica {
+        let replicas: Vec<HashMap<String, String>> = redis::cmd("SENTINEL")
+            .arg("REPLICAS")
+            .arg(&config.master_name)
+            .query_async(conn)
+            .await?;
+
+        return pick_replica(&replicas)
+            .ok_or_else(|| sentinel_error("No replica available", config.master_name.clone()));
+    }
+
+    let master: Option<Node> = redis::cmd("SENTINEL")
+        .arg("GET-MASTER-ADDR-BY-NAME")
+        .arg(&config.master_name)
+        .query_async(conn)
+        .await?;
+
+    master.ok_or_else(|| sentinel_error("Unknown master", config.master_name.clone()))
+}
+
+/// The first replica in a SENTINEL REPLICAS reply that is up and connected.
+fn pick_replica(replicas: &[HashMap<String, String>]) -> Option<Node> {
+    replicas
+        .iter()
+        .find(|replica| {
+            replica.get("flags").is_some_and(|flags| {
+                !flags
+                    .split(',')
+                    .any(|flag| matches!(flag, "s_down" | "o_down" | "disconnected"))
+            })
+        })
+        .and_then(|replica| {
+            Some((
+                replica.get("ip")?.clone(),
+                replica.get("port")?.parse().ok()?,
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replica(ip: &str, port: &str, flags: &str) -> HashMap<String, String> {
+        HashMap::from([
+            (String::from("ip"), ip.to_string()),
+            (String::from("port"), port.to_string()),
+            (String::from("flags"), flags.to_string()),
+        ])
+    }
+
+    #[test]
+    fn replica_choice() {
+        let replicas = vec![
+            replica("10.0.0.1", "6379", "s_down,slave"),
+            replica("10.0.0.2", "6379", "slave,disconnected"),
+            replica("10.0.0.3", "6380", "slave"),
+        ];
+        assert_eq!(
+            pick_replica(&replicas),
+            Some((String::from("10.0.0.3"), 6380))
+        );
+
+        assert_eq!(pick_replica(&replicas[..2]), None);
+        assert_eq!(pick_replica(&[]), None);
+    }
+}
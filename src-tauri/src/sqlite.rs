@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use rusqlite::{params, Connection, Result as SqliteResult};
+use rusqlite::{params, Connection, Result as SqliteResult, ToSql};
 use serde::{de::DeserializeOwned, Serialize};
 
 #[derive(Default)]
@@ -103,11 +103,22 @@ impl SqliteManager {
     }
 
     pub fn list<T: Entity>(&self) -> SqliteResult<Vec<T>> {
+        self.query("1", &[])
+    }
+
+    /// Entities matching `filter`, an SQL expression that may be followed by
+    /// ORDER BY and LIMIT clauses. Fields are reachable through
+    /// `json_extract(data, '$.field')`.
+    pub fn query<T: Entity>(&self, filter: &str, params: &[&dyn ToSql]) -> SqliteResult<Vec<T>> {
         let conn = self.connect()?;
-        let mut stmt = conn.prepare(&format!("SELECT id, data FROM {}", T::get_table_name()))?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, data FROM {} WHERE {}",
+            T::get_table_name(),
+            filter
+        ))?;
 
         let entities = stmt
-            .query_map([], |row| {
+            .query_map(params, |row| {
                 let id: i64 = row.get(0)?;
                 let data: String = row.get(1)?;
                 let mut json_value: serde_json::Value = serde_json::from_str(&data).unwrap();
@@ -124,6 +135,19 @@ impl SqliteManager {
 
         Ok(entities)
     }
+
+    /// Deletes the entities matching `filter`, returning how many were removed.
+    pub fn delete_where<T: Entity>(
+        &self,
+        filter: &str,
+        params: &[&dyn ToSql],
+    ) -> SqliteResult<usize> {
+        let conn = self.connect()?;
+        conn.execute(
+            &format!("DELETE FROM {} WHERE {}", T::get_table_name(), filter),
+            params,
+        )
+    }
 }
 
 #[cfg(test)]
@@ -236,6 +260,37 @@ mod tests {
         cleanup();
     }
 
+    #[test]
+    #[serial]
+    fn filtered_queries() {
+        let manager = setup();
+
+        for name in ["alpha", "beta", "alphabet"] {
+            manager
+                .insert(&TestEntity {
+                    id: 0,
+                    name: name.to_string(),
+                })
+                .unwrap();
+        }
+
+        let found = manager
+            .query::<TestEntity>(
+                "instr(json_extract(data, '$.name'), ?) > 0 ORDER BY id DESC",
+                params!["alpha"],
+            )
+            .unwrap();
+        let names: Vec<&str> = found.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["alphabet", "alpha"]);
+
+        let deleted = manager
+            .delete_where::<TestEntity>("json_extract(data, '$.name') = ?", params!["beta"])
+            .unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(manager.list::<TestEntity>().unwrap().len(), 2);
+        cleanup();
+    }
+
     #[test]
     #[serial]
     fn init_table() {
@@ -0,0 +1,450 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use polling::{Event, Events, Poller};
+use ssh2::{
+    BlockDirections, Channel, CheckResult, ErrorCode, HashType, KnownHostFileKind, Session,
+};
+
+use crate::ob::SshConfig;
+
+const SSH_TIMEOUT: Duration = Duration::from_secs(10);
+const KEEPALIVE_INTERVAL: u32 = 30;
+const BUFFER_SIZE: usize = 16 * 1024;
+/// Poller keys of the listener and the SSH socket. Local connections get the
+/// keys after them.
+const LISTENER_KEY: usize = 0;
+const SESSION_KEY: usize = 1;
+/// LIBSSH2_ERROR_EAGAIN, what a non-blocking session returns for a call that
+/// has to be retried.
+const EAGAIN: i32 = -37;
+
+/// A local port forwarded to a host reachable from an SSH server. Every
+/// connection to it goes over one SSH session, driven by a single thread
+/// since a libssh2 session cannot be used from several at once. The session
+/// is closed when the tunnel is dropped, and `closed` is set once the thread
+/// has stopped, including when the server ends the session.
+pub struct SshTunnel {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+    poller: Arc<Poller>,
+}
+
+impl SshTunnel {
+    /// Logs in to the server in `config` and forwards to `host:port` as seen
+    /// from there. A channel is opened up front so a target the server cannot
+    /// reach is reported here. Blocks until both are done.
+    pub fn open(config: &SshConfig, host: &str, port: u16) -> io::Result<Self> {
+        let (session, socket) = login(config)?;
+        session.channel_direct_tcpip(host, port, None)?.close()?;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let poller = Arc::new(Poller::new()?);
+        // Both are deleted from the poller when the forwarder stops, before
+        // it drops them.
+        unsafe {
+            poller.add(&listener, Event::none(LISTENER_KEY))?;
+            poller.add(&socket, Event::none(SESSION_KEY))?;
+        }
+
+        let forwarder = Forwarder {
+            session,
+            socket,
+            listener,
+            poller: poller.clone(),
+            host: host.to_string(),
+            port,
+            stop: stop.clone(),
+            closed: closed.clone(),
+        };
+        thread::spawn(move || forwarder.run());
+
+        Ok(SshTunnel {
+            local_addr,
+            stop,
+            closed,
+            poller,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Whether the tunnel has stopped forwarding, after the SSH session ended
+    /// or failed. It has to be opened again.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.poller.notify();
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Connects to the server in `config` and runs the key exchange, returning
+/// the session along with a handle on its socket.
+fn handshake(config: &SshConfig) -> io::Result<(Session, TcpStream)> {
+    let addr = (config.host.as_str(), config.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid_input(format!("Unknown SSH host: {}", config.host)))?;
+    let stream = TcpStream::connect_timeout(&addr, SSH_TIMEOUT)?;
+    let socket = stream.try_clone()?;
+
+    let mut session = Session::new()?;
+    session.set_timeout(SSH_TIMEOUT.as_millis() as u32);
+    session.set_tcp_stream(stream);
+    session.handshake()?;
+    Ok((session, socket))
+}
+
+fn fingerprint(session: &Session) -> io::Result<String> {
+    session
+        .host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)))
+        .ok_or_else(|| io::Error::other("SSH server sent no host key"))
+}
+
+/// The fingerprint of the host key of the server in `config`, in the format
+/// ssh prints, for the user to compare and confirm before connecting.
+pub fn host_key_fingerprint(config: &SshConfig) -> io::Result<String> {
+    let (session, _) = handshake(config)?;
+    fingerprint(&session)
+}
+
+fn login(config: &SshConfig) -> io::Result<(Session, TcpStream)> {
+    let (session, socket) = handshake(config)?;
+    check_host_key(&session, config)?;
+
+    match (&config.private_key_path, &config.password) {
+        (Some(key), _) => session.userauth_pubkey_file(
+            &config.user,
+            None,
+            Path::new(key),
+            config.passphrase.as_deref(),
+        )?,
+        (None, Some(password)) => session.userauth_password(&config.user, password)?,
+        (None, None) => {
+            return Err(invalid_input(String::from(
+                "SSH needs a password or a private key",
+            )))
+        }
+    }
+
+    if !session.authenticated() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "SSH authentication failed",
+        ));
+    }
+
+    session.set_keepalive(true, KEEPALIVE_INTERVAL);
+    Ok((session, socket))
+}
+
+/// Accepts the server when its key has the fingerprint confirmed in
+/// `config`, or, when none was, when ~/.ssh/known_hosts has the key for it.
+/// Unknown and changed keys are rejected with their fingerprint in the
+/// error.
+fn check_host_key(session: &Session, config: &SshConfig) -> io::Result<()> {
+    let fingerprint = fingerprint(session)?;
+    let rejected = |message: &str| {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} {}: {}", message, config.host, fingerprint),
+        )
+    };
+
+    match &config.host_key_fingerprint {
+        Some(confirmed) if *confirmed == fingerprint => Ok(()),
+        Some(_) => Err(rejected("Host key changed for")),
+        None => match known_host(session, &config.host, config.port) {
+            Some(CheckResult::Match) => Ok(()),
+            Some(CheckResult::Mismatch) => Err(rejected("Host key does not match known_hosts for")),
+            _ => Err(rejected("Unknown host key for")),
+        },
+    }
+}
+
+/// How the server's key compares to ~/.ssh/known_hosts, None without the
+/// file.
+fn known_host(session: &Session, host: &str, port: u16) -> Option<CheckResult> {
+    let home = home_dir()?;
+    let (key, _) = session.host_key()?;
+
+    let mut known_hosts = session.known_hosts().ok()?;
+    known_hosts
+        .read_file(
+            &home.join(".ssh").join("known_hosts"),
+            KnownHostFileKind::OpenSSH,
+        )
+        .ok()?;
+    Some(known_hosts.check_port(host, port, key))
+}
+
+fn would_block(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock
+}
+
+fn ssh_would_block(e: &ssh2::Error) -> bool {
+    e.code() == ErrorCode::Session(EAGAIN)
+}
+
+/// One local connection and the channel it is forwarded over, with the data
+/// read from either side that the other has not taken yet.
+struct Forward {
+    key: usize,
+    client: TcpStream,
+    channel: Channel,
+    to_channel: Vec<u8>,
+    to_client: Vec<u8>,
+}
+
+impl Forward {
+    /// Moves whatever data is ready in both directions. Returns whether any
+    /// did, or an error once either side is closed.
+    fn pump(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut moved = false;
+
+        if self.to_channel.is_empty() {
+            match self.client.read(buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.to_channel.extend_from_slice(&buf[..n]),
+                Err(e) if would_block(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !self.to_channel.is_empty() {
+            match self.channel.write(&self.to_channel) {
+                Ok(n) => {
+                    self.to_channel.drain(..n);
+                    moved = true;
+                }
+                Err(e) if would_block(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if self.to_client.is_empty() {
+            match self.channel.read(buf) {
+                Ok(0) if self.channel.eof() => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.to_client.extend_from_slice(&buf[..n]),
+                Err(e) if would_block(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !self.to_client.is_empty() {
+            match self.client.write(&self.to_client) {
+                Ok(n) => {
+                    self.to_client.drain(..n);
+                    moved = true;
+                }
+                Err(e) if would_block(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(moved)
+    }
+}
+
+/// The thread behind an `SshTunnel`. The session is non-blocking, so
+/// accepting, opening channels, copying data and closing channels are all
+/// retried on each pass until they go through. Between passes that move
+/// nothing it waits for one of the sockets to become ready. It stops, setting
+/// `closed`, when asked to or once the server closes the session.
+struct Forwarder {
+    session: Session,
+    socket: TcpStream,
+    listener: TcpListener,
+    poller: Arc<Poller>,
+    host: String,
+    port: u16,
+    stop: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Forwarder {
+    fn run(self) {
+        self.session.set_blocking(false);
+        if let Err(e) = self.socket.set_nonblocking(true) {
+            log::error!("SSH tunnel cannot watch the session: {}", e);
+            self.stop.store(true, Ordering::SeqCst);
+        }
+
+        let mut waiting: Vec<TcpStream> = Vec::new();
+        let mut forwards: Vec<Forward> = Vec::new();
+        let mut closing: Vec<Channel> = Vec::new();
+        let mut buf = vec![0; BUFFER_SIZE];
+        let mut events = Events::new();
+        let mut next_key = SESSION_KEY + 1;
+
+        while !self.stop.load(Ordering::SeqCst) {
+            let mut moved = false;
+
+            match self.listener.accept() {
+                Ok((client, _)) => {
+                    if client.set_nonblocking(true).is_ok() {
+                        waiting.push(client);
+                    }
+                    moved = true;
+                }
+                Err(e) if would_block(&e) => {}
+                Err(e) => {
+                    log::error!("SSH tunnel stopped accepting connections: {}", e);
+                    break;
+                }
+            }
+
+            // libssh2 opens one channel at a time, resuming on each call.
+            if !waiting.is_empty() {
+                match self
+                    .session
+                    .channel_direct_tcpip(&self.host, self.port, None)
+                {
+                    Ok(channel) => {
+                        let client = waiting.remove(0);
+                        // Deleted from the poller before the forward is dropped.
+                        match unsafe { self.poller.add(&client, Event::none(next_key)) } {
+                            Ok(()) => forwards.push(Forward {
+                                key: next_key,
+                                client,
+                                channel,
+                                to_channel: Vec::new(),
+                                to_client: Vec::new(),
+                            }),
+                            Err(e) => {
+                                log::error!("SSH tunnel cannot watch a connection: {}", e);
+                                closing.push(channel);
+                            }
+                        }
+                        next_key += 1;
+                        moved = true;
+                    }
+                    Err(e) if ssh_would_block(&e) => {}
+                    Err(e) => {
+                        log::error!("SSH tunnel to {}:{} failed: {}", self.host, self.port, e);
+                        waiting.remove(0);
+                    }
+                }
+            }
+
+            let mut i = 0;
+            while i < forwards.len() {
+                match forwards[i].pump(&mut buf) {
+                    Ok(pumped) => {
+                        moved |= pumped;
+                        i += 1;
+                    }
+                    Err(_) => {
+                        let forward = forwards.swap_remove(i);
+                        let _ = self.poller.delete(&forward.client);
+                        closing.push(forward.channel);
+                    }
+                }
+            }
+
+            closing.retain_mut(|channel| matches!(channel.close(), Err(e) if ssh_would_block(&e)));
+
+            if !moved {
+                let busy = !waiting.is_empty() || !forwards.is_empty() || !closing.is_empty();
+                if let Err(e) = self.wait(&forwards, busy, &mut events) {
+                    log::error!("SSH tunnel to {}:{} closed: {}", self.host, self.port, e);
+                    break;
+                }
+            }
+        }
+
+        self.closed.store(true, Ordering::SeqCst);
+        for forward in &forwards {
+            let _ = self.poller.delete(&forward.client);
+        }
+        let _ = self.poller.delete(&self.listener);
+        let _ = self.poller.delete(&self.socket);
+
+        self.session.set_blocking(true);
+        let _ = self.session.disconnect(None, "Tunnel closed", None);
+    }
+
+    /// Whether the server sent data that libssh2 has not read yet. Fails once
+    /// the server has closed the session or the socket has failed, which the
+    /// poller would otherwise keep reporting as readable.
+    fn session_pending(&self) -> io::Result<bool> {
+        match self.socket.peek(&mut [0]) {
+            Ok(0) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "SSH server closed the session",
+            )),
+            Ok(_) => Ok(true),
+            Err(e) if would_block(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sleeps until a new connection comes in, the session can go on, a
+    /// connection has data to forward or room for data waiting for it, or a
+    /// keepalive is due. Data the server sent is only waited for while it is
+    /// not already there, or while `busy` channels will have libssh2 read it,
+    /// since nothing else takes it off the socket. Fails once the session has
+    /// ended.
+    fn wait(&self, forwards: &[Forward], busy: bool, events: &mut Events) -> io::Result<()> {
+        let pending = self.session_pending()?;
+        let receiving = busy || !pending;
+        let sending = matches!(
+            self.session.block_directions(),
+            BlockDirections::Outbound | BlockDirections::Both
+        );
+
+        self.poller
+            .modify(&self.listener, Event::readable(LISTENER_KEY))?;
+        self.poller
+            .modify(&self.socket, Event::new(SESSION_KEY, receiving, sending))?;
+        for forward in forwards {
+            let interest = Event::new(
+                forward.key,
+                forward.to_channel.is_empty(),
+                !forward.to_client.is_empty(),
+            );
+            self.poller.modify(&forward.client, interest)?;
+        }
+
+        let keepalive = match self.session.keepalive_send() {
+            Ok(seconds) => seconds,
+            Err(e) if ssh_would_block(&e) => KEEPALIVE_INTERVAL,
+            Err(e) => return Err(e.into()),
+        };
+        events.clear();
+        self.poller
+            .wait(events, Some(Duration::from_secs(keepalive.max(1).into())))?;
+        Ok(())
+    }
+}
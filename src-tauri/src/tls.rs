@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::aio::{ConnectionLike, MultiplexedConnection};
+use redis::{Cmd, ConnectionAddr, ConnectionInfo, Pipeline, RedisFuture, RedisResult, Value};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::crypto;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::ob::TlsConfig;
+
+const TLS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TLS client that checks the server certificate against `server_name`
+/// instead of the host it connects to. redis-rs can only verify the host it
+/// dials, which is 127.0.0.1 through an SSH tunnel and whatever address the
+/// sentinels report, so connections with a server name make their own TLS
+/// streams with one of these.
+pub struct NamedTls {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl NamedTls {
+    /// Reads the certificates named in `config`.
+    pub fn new(config: &TlsConfig, server_name: &str) -> io::Result<Self> {
+        Ok(NamedTls {
+            connector: TlsConnector::from(Arc::new(client_config(config)?)),
+            server_name: ServerName::try_from(server_name.to_string())
+                .map_err(|_| invalid_input(format!("Invalid TLS server name: {}", server_name)))?,
+        })
+    }
+
+    /// Connects to `host:port` and makes the handshake.
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<TlsStream<TcpStream>> {
+        let stream = TcpStream::connect((host, port)).await?;
+        self.connector
+            .connect(self.server_name.clone(), stream)
+            .await
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn open_pem(path: &str) -> io::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
+
+fn read_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut open_pem(path)?).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(invalid_input(format!("No certificate found in {}", path)));
+    }
+    Ok(certs)
+}
+
+/// The rustls settings redis-rs would make from `config`: its CA or else the
+/// web PKI roots, and the client certificate if there is one.
+fn client_config(config: &TlsConfig) -> io::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    match &config.ca_path {
+        Some(path) => {
+            for cert in read_certs(path)? {
+                roots.add(cert).map_err(io::Error::other)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let builder = ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots);
+
+    match (&config.client_cert_path, &config.client_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = read_certs(cert_path)?;
+            let key = rustls_pemfile::private_key(&mut open_pem(key_path)?)?
+                .ok_or_else(|| invalid_input(format!("No private key found in {}", key_path)))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(io::Error::other)
+        }
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err(invalid_input(String::from(
+            "A client certificate needs both a certificate and a key",
+        ))),
+    }
+}
+
+/// A multiplexed connection over a `NamedTls` stream to the address in
+/// `info`, which is opened again on the next command after it drops, as a
+/// connection manager does. Clones share the connection.
+#[derive(Clone)]
+pub struct NamedTlsConnection {
+    info: Arc<ConnectionInfo>,
+    tls: Arc<NamedTls>,
+    conn: Arc<tokio::sync::Mutex<Option<MultiplexedConnection>>>,
+}
+
+impl NamedTlsConnection {
+    /// Connects right away, so a wrong certificate is reported here.
+    pub async fn open(info: ConnectionInfo, tls: Arc<NamedTls>) -> RedisResult<Self> {
+        let conn = NamedTlsConnection {
+            info: Arc::new(info),
+            tls,
+            conn: Arc::default(),
+        };
+        conn.connection().await?;
+        Ok(conn)
+    }
+
+    /// The open connection, or a new one when there is none.
+    async fn connection(&self) -> RedisResult<MultiplexedConnection> {
+        let mut conn = self.conn.lock().await;
+        if let Some(conn) = &*conn {
+            return Ok(conn.clone());
+        }
+
+        let ConnectionAddr::Tcp(host, port) = &self.info.addr else {
+            return Err(invalid_input(String::from("TLS needs a TCP address")).into());
+        };
+        let opened = tokio::time::timeout(TLS_TIMEOUT, async {
+            let stream = self.tls.connect(host, *port).await?;
+            MultiplexedConnection::new(&self.info.redis, stream).await
+        })
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+        let (opened, driver) = opened;
+        tokio::spawn(driver);
+        *conn = Some(opened.clone());
+        Ok(opened)
+    }
+
+    /// Drops the connection when `result` shows it is broken.
+    async fn check<T>(&self, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(e) = &result {
+            if e.is_connection_dropped() || e.is_io_error() || e.is_unrecoverable_error() {
+                self.conn.lock().await.take();
+            }
+        }
+        result
+    }
+}
+
+impl ConnectionLike for NamedTlsConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let mut conn = self.connection().await?;
+            let result = conn.req_packed_command(cmd).await;
+            self.check(result).await
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let mut conn = self.connection().await?;
+            let result = conn.req_packed_commands(cmd, offset, count).await;
+            self.check(result).await
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.info.redis.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tls_fixture(name: &str) -> Option<String> {
+        Some(format!("{}/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name))
+    }
+
+    fn test_tls_config() -> TlsConfig {
+        TlsConfig {
+            ca_path: tls_fixture("ca.crt"),
+            client_cert_path: tls_fixture("client.crt"),
+            client_key_path: tls_fixture("client.key"),
+            server_name: Some(String::from("redis.test")),
+            insecure: false,
+        }
+    }
+
+    #[test]
+    fn config_errors() {
+        assert!(NamedTls::new(&test_tls_config(), "redis.test").is_ok());
+        assert!(NamedTls::new(&test_tls_config(), "not a name!").is_err());
+
+        let missing_key = TlsConfig {
+            client_key_path: None,
+            ..test_tls_config()
+        };
+        assert!(NamedTls::new(&missing_key, "redis.test").is_err());
+
+        let not_a_key = TlsConfig {
+            client_key_path: tls_fixture("client.crt"),
+            ..test_tls_config()
+        };
+        assert!(NamedTls::new(&not_a_key, "redis.test").is_err());
+
+        let missing_file = TlsConfig {
+            ca_path: tls_fixture("missing.crt"),
+            ..test_tls_config()
+        };
+        let error = NamedTls::new(&missing_file, "redis.test").err().unwrap();
+        assert!(error.to_string().contains("missing.crt"));
+    }
+
+    /// A server with the test certificate, issued for redis.test only, that
+    /// makes one handshake.
+    async fn tls_server() -> std::net::SocketAddr {
+        use tokio_rustls::rustls::ServerConfig;
+
+        let certs = read_certs(&tls_fixture("server.crt").unwrap()).unwrap();
+        let key = rustls_pemfile::private_key(
+            &mut open_pem(&tls_fixture("server.key").unwrap()).unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+        let config =
+            ServerConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(certs, key)
+                .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let _ = acceptor.accept(stream).await;
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn verifies_server_name() {
+        let addr = tls_server().await;
+        let tls = NamedTls::new(&test_tls_config(), "redis.test").unwrap();
+        assert!(tls.connect("127.0.0.1", addr.port()).await.is_ok());
+
+        let addr = tls_server().await;
+        let tls = NamedTls::new(&test_tls_config(), "localhost").unwrap();
+        assert!(tls.connect("127.0.0.1", addr.port()).await.is_err());
+    }
+}
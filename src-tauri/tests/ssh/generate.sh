@@ -0,0 +1,9 @@
+#!/bin/sh
+# Regenerates the key used by the SSH tunnel tests. The key is protected with
+# the passphrase "setui" and kept in PEM format, which libssh2 reads with any
+# crypto backend.
+set -e
+cd "$(dirname "$0")"
+
+rm -f id_ecdsa id_ecdsa.pub
+ssh-keygen -q -t ecdsa -b 256 -m PEM -N setui -C setui -f id_ecdsa
@@ -0,0 +1,23 @@
+#!/bin/sh
+# Regenerates the certificates used by the TLS tests. The server certificate
+# is only valid for redis.test, so connecting to 127.0.0.1 needs that server
+# name.
+set -e
+cd "$(dirname "$0")"
+
+openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:P-256 -nodes -days 36500 \
+    -subj "/CN=setui test CA" -keyout ca.key -out ca.crt
+
+openssl req -newkey ec -pkeyopt ec_paramgen_curve:P-256 -nodes \
+    -subj "/CN=redis.test" -keyout server.key -out server.csr
+printf "subjectAltName=DNS:redis.test\n" > server.ext
+openssl x509 -req -in server.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days 36500 \
+    -extfile server.ext -out server.crt
+
+openssl req -newkey ec -pkeyopt ec_paramgen_curve:P-256 -nodes \
+    -subj "/CN=setui" -keyout client.key -out client.csr
+printf "extendedKeyUsage=clientAuth\n" > client.ext
+openssl x509 -req -in client.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days 36500 \
+    -extfile client.ext -out client.crt
+
+rm -f ca.key ca.srl server.csr server.ext client.csr client.ext
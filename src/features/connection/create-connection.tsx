@@ -56,9 +56,12 @@ const CreateConnection = () => {
       setIsSubmitting(true);
 
       const connectionId = await invoke("create_connection", {
-        uri: data.uri,
-        name: data.name,
-        color: data.color,
+        entity: {
+          id: 0,
+          uri_connection: data.uri,
+          name: data.name,
+          color: data.color,
+        },
       });
 
       await invoke("connect_redis", {
@@ -107,7 +107,7 @@ const KeyView = () => {
         onTtlUpdate={handleTtlUpdate}
       />
       <KeyContent
-        value={selectedKey.value}
+        value={selectedKey.value.data}
         editedValue={editedValue}
         onValueChange={setEditedValue}
         onSave={handleSave}
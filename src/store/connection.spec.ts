@@ -220,7 +220,7 @@ describe("Connection Store", () => {
     useConnectionStore.getState().addConnection(connection);
     vi.mocked(invoke).mockResolvedValueOnce(undefined).mockResolvedValueOnce({
       key,
-      value: "test",
+      value: { encoding: "utf8", data: "test" },
       ttl,
       data_type: "string",
     });
@@ -9,9 +9,16 @@ export interface Connection {
   color: string;
 }
 
+export type ValueEncoding = "utf8" | "hex" | "base64";
+
+export interface RedisValue {
+  encoding: ValueEncoding;
+  data: string;
+}
+
 export interface RedisKeyInfo {
   key: string;
-  value: string;
+  value: RedisValue;
   ttl: number;
   data_type: string;
 }
@@ -329,10 +336,13 @@ export const useConnectionStore = create<ConnectionStore>((set, get) => ({
 
   setKeyValue: async (connectionId, key, value) => {
     try {
+      const selectedKey = get().selectedKey;
+      const encoding =
+        selectedKey?.key === key ? selectedKey.value.encoding : "utf8";
       await invoke("set_redis_key", {
         id: connectionId,
         key,
-        value,
+        value: { encoding, data: value },
       });
       await get().getKeyInfo(connectionId, key);
       toast({